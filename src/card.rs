@@ -1,4 +1,6 @@
 use anyhow::Result;
+use std::collections::HashSet;
+use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, Sub, SubAssign};
 
 #[derive(Eq, PartialEq)]
 pub enum Suit {
@@ -27,35 +29,13 @@ pub struct Card {
     pub num: u8,
 }
 
-#[derive(Clone, Hash, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
-pub enum Book {
-    LowDiamonds,  // 2-7
-    HighDiamonds, // 9-A
-    LowClubs,
-    HighClubs,
-    LowHearts,
-    HighHearts,
-    LowSpades,
-    HighSpades,
-    Eights, // Some variants remove the eights
+/// A set of cards stored as one bit per card, iterated in deck order
+#[derive(Clone, Copy, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CardSet {
+    bits: u64,
 }
 
 impl Card {
-    pub fn book(&self) -> Book {
-        match self.num / 6 {
-            0 => Book::LowDiamonds,
-            1 => Book::HighDiamonds,
-            2 => Book::LowClubs,
-            3 => Book::HighClubs,
-            4 => Book::LowHearts,
-            5 => Book::HighHearts,
-            6 => Book::LowSpades,
-            7 => Book::HighSpades,
-            8 => Book::Eights,
-            _ => panic!("Invalid card number"),
-        }
-    }
-
     pub fn suit(&self) -> Option<Suit> {
         if self.num >= 52 {
             None
@@ -153,16 +133,220 @@ impl DisplayCard {
     }
 }
 
-impl Book {
-    pub fn cards(&self) -> Vec<Card> {
-        let offset = *self as u8;
-        let mut output = vec![];
-        for i in 0..6 {
-            output.push(Card {
-                num: offset * 6 + i,
-            });
+impl CardSet {
+    pub fn new() -> Self {
+        CardSet { bits: 0 }
+    }
+
+    /// The first `num_cards` cards of the deck
+    pub fn full(num_cards: usize) -> Self {
+        CardSet {
+            bits: if num_cards >= 64 {
+                u64::MAX
+            } else {
+                (1 << num_cards) - 1
+            },
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bits.count_ones() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits == 0
+    }
+
+    pub fn contains(&self, card: Card) -> bool {
+        self.bits & (1 << card.num) != 0
+    }
+
+    /// Returns whether the card was not already in the set
+    pub fn insert(&mut self, card: Card) -> bool {
+        let absent = !self.contains(card);
+        self.bits |= 1 << card.num;
+        absent
+    }
+
+    /// Returns whether the card was in the set
+    pub fn remove(&mut self, card: Card) -> bool {
+        let present = self.contains(card);
+        self.bits &= !(1 << card.num);
+        present
+    }
+
+    pub fn retain(&mut self, mut f: impl FnMut(Card) -> bool) {
+        for card in self.iter() {
+            if !f(card) {
+                self.remove(card);
+            }
+        }
+    }
+
+    pub fn union(&self, other: CardSet) -> CardSet {
+        CardSet {
+            bits: self.bits | other.bits,
+        }
+    }
+
+    pub fn intersection(&self, other: CardSet) -> CardSet {
+        CardSet {
+            bits: self.bits & other.bits,
+        }
+    }
+
+    pub fn difference(&self, other: CardSet) -> CardSet {
+        CardSet {
+            bits: self.bits & !other.bits,
+        }
+    }
+
+    pub fn intersects(&self, other: CardSet) -> bool {
+        self.bits & other.bits != 0
+    }
+
+    pub fn is_subset(&self, other: CardSet) -> bool {
+        self.bits & !other.bits == 0
+    }
+
+    /// Lowest card in deck order
+    pub fn first(&self) -> Option<Card> {
+        self.iter().next()
+    }
+
+    pub fn iter(&self) -> CardSetIter {
+        CardSetIter { bits: self.bits }
+    }
+
+    pub fn to_vec(self) -> Vec<Card> {
+        self.iter().collect()
+    }
+}
+
+pub struct CardSetIter {
+    bits: u64,
+}
+
+impl Iterator for CardSetIter {
+    type Item = Card;
+
+    fn next(&mut self) -> Option<Card> {
+        if self.bits == 0 {
+            return None;
         }
-        output
+        let num = self.bits.trailing_zeros() as u8;
+        self.bits &= self.bits - 1;
+        Some(Card { num })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.bits.count_ones() as usize;
+        (len, Some(len))
+    }
+}
+
+impl IntoIterator for CardSet {
+    type Item = Card;
+    type IntoIter = CardSetIter;
+
+    fn into_iter(self) -> CardSetIter {
+        self.iter()
+    }
+}
+
+impl FromIterator<Card> for CardSet {
+    fn from_iter<I: IntoIterator<Item = Card>>(iter: I) -> Self {
+        let mut set = CardSet::new();
+        iter.into_iter().for_each(|card| {
+            set.insert(card);
+        });
+        set
+    }
+}
+
+impl Extend<Card> for CardSet {
+    fn extend<I: IntoIterator<Item = Card>>(&mut self, iter: I) {
+        iter.into_iter().for_each(|card| {
+            self.insert(card);
+        });
+    }
+}
+
+impl From<Card> for CardSet {
+    fn from(card: Card) -> Self {
+        CardSet {
+            bits: 1 << card.num,
+        }
+    }
+}
+
+impl From<&[Card]> for CardSet {
+    fn from(cards: &[Card]) -> Self {
+        cards.iter().copied().collect()
+    }
+}
+
+impl From<Vec<Card>> for CardSet {
+    fn from(cards: Vec<Card>) -> Self {
+        cards.into_iter().collect()
+    }
+}
+
+impl From<&HashSet<Card>> for CardSet {
+    fn from(cards: &HashSet<Card>) -> Self {
+        cards.iter().copied().collect()
+    }
+}
+
+impl From<HashSet<Card>> for CardSet {
+    fn from(cards: HashSet<Card>) -> Self {
+        cards.into_iter().collect()
+    }
+}
+
+impl From<CardSet> for Vec<Card> {
+    fn from(cards: CardSet) -> Self {
+        cards.to_vec()
+    }
+}
+
+// Set algebra
+impl BitOr for CardSet {
+    type Output = CardSet;
+    fn bitor(self, rhs: CardSet) -> CardSet {
+        self.union(rhs)
+    }
+}
+
+impl BitAnd for CardSet {
+    type Output = CardSet;
+    fn bitand(self, rhs: CardSet) -> CardSet {
+        self.intersection(rhs)
+    }
+}
+
+impl Sub for CardSet {
+    type Output = CardSet;
+    fn sub(self, rhs: CardSet) -> CardSet {
+        self.difference(rhs)
+    }
+}
+
+impl BitOrAssign for CardSet {
+    fn bitor_assign(&mut self, rhs: CardSet) {
+        self.bits |= rhs.bits;
+    }
+}
+
+impl BitAndAssign for CardSet {
+    fn bitand_assign(&mut self, rhs: CardSet) {
+        self.bits &= rhs.bits;
+    }
+}
+
+impl SubAssign for CardSet {
+    fn sub_assign(&mut self, rhs: CardSet) {
+        self.bits &= !rhs.bits;
     }
 }
 
@@ -212,15 +396,23 @@ impl std::fmt::Display for Card {
     }
 }
 
+// Comma separated so a set reads back as a single token
+impl std::fmt::Display for CardSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let cards: Vec<String> = self.iter().map(|card| card.to_string()).collect();
+        write!(f, "{}", cards.join(","))
+    }
+}
+
 // FromStr
 impl std::str::FromStr for Suit {
     type Err = ParseSuitError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_uppercase().as_str() {
-            "D" => Ok(Suit::Diamonds),
-            "C" => Ok(Suit::Clubs),
-            "H" => Ok(Suit::Hearts),
-            "S" => Ok(Suit::Spades),
+            "D" | "♦" => Ok(Suit::Diamonds),
+            "C" | "♣" => Ok(Suit::Clubs),
+            "H" | "♥" => Ok(Suit::Hearts),
+            "S" | "♠" => Ok(Suit::Spades),
             _ => Err(ParseSuitError),
         }
     }
@@ -260,10 +452,13 @@ impl std::str::FromStr for DisplayCard {
         }
 
         // Try to split the string into rank and suit
-        if s.len() < 2 {
+        let Some((suit_idx, _)) = s.char_indices().last() else {
+            return Err(ParseCardError);
+        };
+        if suit_idx == 0 {
             return Err(ParseCardError);
         }
-        let (rank_str, suit_str) = s.split_at(s.len() - 1);
+        let (rank_str, suit_str) = s.split_at(suit_idx);
         let rank = Rank::from_str(rank_str).map_err(|_| ParseCardError)?;
         let suit = Suit::from_str(suit_str).map_err(|_| ParseCardError)?;
         Ok(DisplayCard::Standard { suit, rank })
@@ -279,21 +474,15 @@ impl std::str::FromStr for Card {
     }
 }
 
-impl std::str::FromStr for Book {
-    type Err = ParseBookError;
+// Accepts cards separated by commas and/or whitespace
+impl std::str::FromStr for CardSet {
+    type Err = ParseCardError;
+
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_uppercase().as_str() {
-            "LOWDIAMONDS" | "LD" => Ok(Book::LowDiamonds),
-            "HighDiamonds" | "HD" => Ok(Book::HighDiamonds),
-            "LOWCLUBS" | "LC" => Ok(Book::LowClubs),
-            "HIGHCLUBS" | "HC" => Ok(Book::HighClubs),
-            "LOWHEARTS" | "LH" => Ok(Book::LowHearts),
-            "HIGHHEARTS" | "HH" => Ok(Book::HighHearts),
-            "LOWSPADES" | "LS" => Ok(Book::LowSpades),
-            "HIGHSPADES" | "HS" => Ok(Book::HighSpades),
-            "EIGHTS" | "E" => Ok(Book::Eights),
-            _ => Err(ParseBookError),
-        }
+        s.split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|token| !token.is_empty())
+            .map(|token| token.parse::<Card>())
+            .collect()
     }
 }
 
@@ -304,6 +493,12 @@ impl std::fmt::Debug for Card {
     }
 }
 
+impl std::fmt::Debug for CardSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
 // Error handling
 #[derive(Debug, PartialEq, Eq)]
 pub struct ParseSuitError;
@@ -314,17 +509,12 @@ pub struct ParseRankError;
 #[derive(Debug, PartialEq, Eq)]
 pub struct ParseCardError;
 
-#[derive(Debug, PartialEq, Eq)]
-pub struct ParseBookError;
-
 impl std::error::Error for ParseSuitError {}
 
 impl std::error::Error for ParseRankError {}
 
 impl std::error::Error for ParseCardError {}
 
-impl std::error::Error for ParseBookError {}
-
 impl std::fmt::Display for ParseSuitError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Failed to parse suit")
@@ -342,9 +532,3 @@ impl std::fmt::Display for ParseCardError {
         write!(f, "Failed to parse card")
     }
 }
-
-impl std::fmt::Display for ParseBookError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Failed to parse book")
-    }
-}
@@ -0,0 +1,111 @@
+// The client side of the protocol spoken by `server`. Each line from the
+// server is read into a `ServerMessage` and gathered into a `Table`, all a
+// seat gets to know of the game
+use crate::card::CardSet;
+use crate::deck::Deck;
+use crate::game::Event;
+use crate::record::parse_event;
+use crate::rules::Rules;
+use std::io::{BufRead, BufReader};
+use std::net::TcpStream;
+use std::sync::Mutex;
+
+/// One line sent by the server
+#[derive(Clone, Debug)]
+pub enum ServerMessage {
+    Rules(Rules),
+    /// Seats nobody has claimed
+    Free(Vec<usize>),
+    /// The seat this connection holds
+    Seat(usize),
+    Start,
+    Hand(CardSet),
+    /// Number of cards each seat holds
+    Sizes(Vec<usize>),
+    Turn(usize),
+    Event(Event),
+    Error(String),
+    /// Books won by each team
+    Over([usize; 2]),
+}
+
+impl ServerMessage {
+    /// Read a line, naming books by the deck in play. None if it cannot be
+    /// read
+    pub fn parse(line: &str, deck: &'static Deck) -> Option<Self> {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let seats = |tokens: &[&str]| -> Option<Vec<usize>> {
+            tokens.iter().map(|t| t.parse().ok()).collect()
+        };
+        Some(match tokens[..] {
+            ["rules", ref rest @ ..] => ServerMessage::Rules(rest.join(" ").parse().ok()?),
+            ["free", ref rest @ ..] => ServerMessage::Free(seats(rest)?),
+            ["seat", seat] => ServerMessage::Seat(seat.parse().ok()?),
+            ["start"] => ServerMessage::Start,
+            ["hand"] => ServerMessage::Hand(CardSet::new()),
+            ["hand", cards] => ServerMessage::Hand(cards.parse().ok()?),
+            ["sizes", ref rest @ ..] => ServerMessage::Sizes(seats(rest)?),
+            ["turn", seat] => ServerMessage::Turn(seat.parse().ok()?),
+            ["event", ref rest @ ..] => ServerMessage::Event(parse_event(rest, deck)?),
+            ["error", ref rest @ ..] => ServerMessage::Error(rest.join(" ")),
+            ["over", a, b] => ServerMessage::Over([a.parse().ok()?, b.parse().ok()?]),
+            _ => return None,
+        })
+    }
+}
+
+/// What one connection knows of the game
+#[derive(Clone, Debug, Default)]
+pub struct Table {
+    pub rules: Rules,
+    pub seat: Option<usize>,
+    pub started: bool,
+    pub hand: CardSet,
+    pub sizes: Vec<usize>,
+    pub turn: Option<usize>,
+    /// Every move seen since connecting
+    pub log: Vec<Event>,
+    /// Books won by each team once the game is over
+    pub result: Option<[usize; 2]>,
+}
+
+impl Table {
+    pub fn update(&mut self, message: &ServerMessage) {
+        match message {
+            ServerMessage::Rules(rules) => self.rules = rules.clone(),
+            ServerMessage::Seat(seat) => self.seat = Some(*seat),
+            ServerMessage::Start => self.started = true,
+            ServerMessage::Hand(hand) => self.hand = *hand,
+            ServerMessage::Sizes(sizes) => self.sizes = sizes.clone(),
+            ServerMessage::Turn(seat) => self.turn = Some(*seat),
+            ServerMessage::Event(event) => self.log.push(event.clone()),
+            ServerMessage::Over(books) => self.result = Some(*books),
+            ServerMessage::Free(_) | ServerMessage::Error(_) => {}
+        }
+    }
+
+    /// The deck the server plays with
+    pub fn deck(&self) -> &'static Deck {
+        self.rules.deck()
+    }
+}
+
+/// Read what the server sends until it closes the connection, keeping the
+/// table up to date. `on_message` is called with the table as it was before
+/// each message. A line that cannot be read is passed on as an error
+pub fn listen(
+    stream: TcpStream,
+    table: &Mutex<Table>,
+    mut on_message: impl FnMut(&Table, &ServerMessage),
+) {
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let mut table = table.lock().unwrap();
+        let message = ServerMessage::parse(&line, table.deck())
+            .unwrap_or_else(|| ServerMessage::Error(format!("Cannot read {line}")));
+        on_message(&table, &message);
+        table.update(&message);
+    }
+}
@@ -0,0 +1,241 @@
+// A deck lists the cards dealt and groups them into books. Cards keep the
+// numbering of the full 54-card pack in every deck, so they read and print
+// the same whatever is played; a deck only chooses which of them are dealt
+// and which book each one belongs to
+use crate::card::{Card, CardSet, Rank, Suit};
+use std::sync::LazyLock;
+
+/// The cards dealt and the books they make up. Books point back at their
+/// deck, so a deck lives for the whole program: use one of the built-in
+/// decks or leak a custom one
+#[derive(Debug)]
+pub struct Deck {
+    pub name: &'static str,
+    cards: CardSet,
+    books: Vec<BookInfo>,
+    // Index into `books` of every card of the deck
+    book_of: [Option<u8>; 64],
+}
+
+#[derive(Debug)]
+struct BookInfo {
+    /// Names accepted when parsing, the first one is shown
+    names: Vec<String>,
+    cards: CardSet,
+}
+
+/// A set of cards declared together, one of the books of a deck
+#[derive(Clone, Copy)]
+pub struct Book {
+    deck: &'static Deck,
+    idx: u8,
+}
+
+static FISH: LazyLock<Deck> = LazyLock::new(|| Deck::build_half_suits(true));
+static LITERATURE: LazyLock<Deck> = LazyLock::new(|| Deck::build_half_suits(false));
+static GO_FISH: LazyLock<Deck> = LazyLock::new(|| Deck::build_ranks(true));
+static GO_FISH_WITHOUT_EIGHTS: LazyLock<Deck> = LazyLock::new(|| Deck::build_ranks(false));
+
+impl Deck {
+    /// A deck from its books, each given by its names and cards. Panics if
+    /// two books share a card
+    pub fn new(name: &'static str, books: Vec<(Vec<String>, CardSet)>) -> Self {
+        let mut cards = CardSet::new();
+        let mut book_of = [None; 64];
+        for (idx, (_, book)) in books.iter().enumerate() {
+            assert!(!cards.intersects(*book), "Books of a deck may not overlap");
+            cards |= *book;
+            for card in book.iter() {
+                book_of[card.num as usize] = Some(idx as u8);
+            }
+        }
+        Deck {
+            name,
+            cards,
+            books: books
+                .into_iter()
+                .map(|(names, cards)| BookInfo { names, cards })
+                .collect(),
+            book_of,
+        }
+    }
+
+    /// Canadian Fish: the low (2-7) and high (9-A) half of every suit, and
+    /// the eights with the jokers. Without the eights this is Literature
+    pub fn half_suits(eights: bool) -> &'static Deck {
+        if eights {
+            &FISH
+        } else {
+            &LITERATURE
+        }
+    }
+
+    /// Go Fish: one book of four for every rank
+    pub fn ranks(eights: bool) -> &'static Deck {
+        if eights {
+            &GO_FISH
+        } else {
+            &GO_FISH_WITHOUT_EIGHTS
+        }
+    }
+
+    fn build_half_suits(eights: bool) -> Deck {
+        // Half suits are numbered in blocks of six, eights and jokers last
+        let names = [
+            ["LD", "LowDiamonds"],
+            ["HD", "HighDiamonds"],
+            ["LC", "LowClubs"],
+            ["HC", "HighClubs"],
+            ["LH", "LowHearts"],
+            ["HH", "HighHearts"],
+            ["LS", "LowSpades"],
+            ["HS", "HighSpades"],
+            ["E", "Eights"],
+        ];
+        let num_books = if eights { 9 } else { 8 };
+        let books = names
+            .iter()
+            .take(num_books)
+            .enumerate()
+            .map(|(i, names)| {
+                let cards = (0..6)
+                    .map(|k| Card {
+                        num: (i * 6 + k) as u8,
+                    })
+                    .collect();
+                (names.iter().map(|name| name.to_string()).collect(), cards)
+            })
+            .collect();
+        Deck::new(if eights { "fish" } else { "literature" }, books)
+    }
+
+    fn build_ranks(eights: bool) -> Deck {
+        let ranks = (2..=10)
+            .map(Rank::Num)
+            .chain([Rank::Jack, Rank::Queen, Rank::King, Rank::Ace])
+            .filter(|rank| eights || *rank != Rank::Num(8));
+        let books = ranks
+            .map(|rank| {
+                let cards = CardSet::full(52)
+                    .iter()
+                    .filter(|card| card.rank().as_ref() == Some(&rank))
+                    .collect();
+                (vec![rank.to_string()], cards)
+            })
+            .collect();
+        Deck::new(if eights { "go-fish" } else { "go-fish-48" }, books)
+    }
+
+    /// Every card dealt
+    pub fn cards(&self) -> CardSet {
+        self.cards
+    }
+
+    pub fn num_books(&self) -> usize {
+        self.books.len()
+    }
+
+    pub fn books(&'static self) -> Vec<Book> {
+        (0..self.books.len())
+            .map(|idx| Book {
+                deck: self,
+                idx: idx as u8,
+            })
+            .collect()
+    }
+
+    /// The book of a card, None if the card is not dealt
+    pub fn book_of(&'static self, card: Card) -> Option<Book> {
+        let idx = self.book_of.get(card.num as usize).copied().flatten()?;
+        Some(Book { deck: self, idx })
+    }
+
+    /// The book going by a name, in any case
+    pub fn book(&'static self, name: &str) -> Result<Book, ParseBookError> {
+        self.books()
+            .into_iter()
+            .find(|book| {
+                self.books[book.index()]
+                    .names
+                    .iter()
+                    .any(|n| n.eq_ignore_ascii_case(name))
+            })
+            .ok_or(ParseBookError)
+    }
+}
+
+impl Book {
+    pub fn mask(&self) -> CardSet {
+        self.deck.books[self.index()].cards
+    }
+
+    pub fn index(&self) -> usize {
+        self.idx as usize
+    }
+
+    pub fn deck(&self) -> &'static Deck {
+        self.deck
+    }
+
+    /// The suit every card of the book shares, if there is one
+    pub fn suit(&self) -> Option<Suit> {
+        let mut suits = self.mask().iter().map(|card| card.suit());
+        let first = suits.next()??;
+        suits
+            .all(|suit| suit.as_ref() == Some(&first))
+            .then_some(first)
+    }
+}
+
+impl PartialEq for Book {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.deck, other.deck) && self.idx == other.idx
+    }
+}
+
+impl Eq for Book {}
+
+impl std::hash::Hash for Book {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.idx.hash(state);
+    }
+}
+
+impl PartialOrd for Book {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Books of one deck are ordered as the deck lists them. Books of different
+// decks never compare equal, as for `eq`
+impl Ord for Book {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        let deck = |book: &Book| book.deck as *const Deck;
+        deck(self).cmp(&deck(other)).then(self.idx.cmp(&other.idx))
+    }
+}
+
+// Short names, as accepted by `Deck::book`
+impl std::fmt::Display for Book {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.deck.books[self.index()].names[0])
+    }
+}
+
+impl std::fmt::Debug for Book {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{self}")
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseBookError;
+
+impl std::error::Error for ParseBookError {}
+
+impl std::fmt::Display for ParseBookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Failed to parse book")
+    }
+}
@@ -1,18 +1,30 @@
-use crate::card::{Book, Card};
-use crate::printer::PrettyDisplay;
-use crate::{Ask, AskOutcome, Declare, Event, Fish};
+use crate::card::{Card, CardSet};
+use crate::deck::{Book, Deck};
+use crate::matching::{feasible_edges, max_matching, repair, Matching};
+use crate::{Ask, AskOutcome, Declare, Event, Fish, PassTurn};
+use rand::Rng;
 use std::cell::RefCell;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::fmt::Debug;
 use std::rc::Rc;
 use std::vec::Vec;
 
 // Once a player is logically excluded from owning a card,
 // they may only gain it again through a public event
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Hand {
-    slots: Vec<Option<Constraint>>,
-    excluded_cards: HashSet<Card>,
+    slots: Vec<SlotState>,
+    excluded_cards: CardSet,
+}
+
+// A slot's constraint together with what the solver knows about it
+#[derive(Clone, Debug)]
+struct SlotState {
+    constraint: Slot,
+    /// Cards this slot holds in at least one consistent deal
+    domain: CardSet,
+    /// Card this slot holds in the witness deal kept by the solver
+    card: Option<Card>,
 }
 
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
@@ -21,51 +33,279 @@ pub enum Constraint {
     IsCard(Card),
 }
 
-// type ProbDist = HashMap<usize, f32>;
+// A fact waiting to be pushed through the hands, with why it holds
+#[derive(Clone, Debug)]
+enum Deduction {
+    Holds(usize, Card, Reason),
+    Lacks(usize, Card, Reason),
+}
+
+/// Why the engine believes a fact. Events are numbered from 1
+#[derive(Clone, Debug)]
+pub enum Reason {
+    /// The card was dealt to the engine's own seat
+    OwnHand,
+    /// The player received the card in the event
+    Received(usize),
+    /// The player asked for the card in the event, so did not hold it
+    Asked(usize),
+    /// The player was asked for the card in the event and did not have it
+    Denied(usize),
+    /// The player asked for a card of the book in the event
+    ShowedBook(usize),
+    /// The book was declared in the event
+    Declared(usize),
+    /// Another player holds the card
+    HeldBy(usize),
+    /// Every other player is ruled out
+    OnlyCandidate,
+    /// The player holds a card of the book and every other card of it is ruled out
+    LastOfBook(Book),
+    /// Hand sizes leave no room otherwise once the event was applied
+    Counting(usize),
+    /// The player passed the turn in the event, so holds no cards
+    Passed(usize),
+}
+
+/// Something the engine can be asked to justify
+#[derive(Clone, Copy, Debug)]
+pub enum Fact {
+    Holds(usize, Card),
+    Lacks(usize, Card),
+    HasBook(usize, Book),
+    Declared(Card),
+    Unknown(usize, Card),
+}
+
+/// A fact, the rule that produced it and the facts that rule relied on
+#[derive(Clone, Debug)]
+pub struct Explanation {
+    pub fact: Fact,
+    pub reason: Option<Reason>,
+    pub premises: Vec<Explanation>,
+}
+
+/// Probability that each player holds a given card
+pub type ProbDist = HashMap<usize, f32>;
+
+/// The cards held by each player in one possible deal
+pub type Deal = HashMap<usize, CardSet>;
+
+/// Which player holds which cards of a book
+pub type Assignment = HashMap<usize, CardSet>;
+
+// Swap attempts per card in play before the first sample and between samples
+const BURN_IN_STEPS: usize = 20;
+const MIXING_STEPS: usize = 4;
+
+// Everything an update can change, kept to roll the update back
+#[derive(Debug)]
+struct Snapshot {
+    hand_map: HashMap<usize, Hand>,
+    out_of_play: CardSet,
+    num_events: usize,
+    reasons: HashMap<(usize, Card), Reason>,
+    book_reasons: HashMap<(usize, Book), usize>,
+}
+
+// Every slot of every player on the left, the cards in play on the right
+struct SlotGraph {
+    live: Vec<Card>,
+    positions: Vec<(usize, usize)>,
+    adj: Vec<Vec<usize>>,
+}
+
+/// Draws consistent deals one at a time, each a few swaps on from the last.
+/// Made by `Engine::deal_sampler`
+#[derive(Clone, Debug)]
+pub struct DealSampler {
+    live: Vec<Card>,
+    // The domain of every slot, grouped by the player it belongs to
+    player_slots: Vec<Vec<CardSet>>,
+    reachable: Vec<CardSet>,
+    hands: Vec<CardSet>,
+    holder: [usize; 64],
+}
+
+impl DealSampler {
+    pub fn next_deal(&mut self, rng: &mut impl Rng) -> Deal {
+        for _ in 0..MIXING_STEPS * self.live.len() {
+            self.step(rng);
+        }
+        self.hands.iter().copied().enumerate().collect()
+    }
+
+    fn valid_hand(&self, player: usize, hand: CardSet) -> bool {
+        let cards = hand.to_vec();
+        let adj: Vec<Vec<usize>> = self.player_slots[player]
+            .iter()
+            .map(|domain| {
+                (0..cards.len())
+                    .filter(|k| domain.contains(cards[*k]))
+                    .collect()
+            })
+            .collect();
+        max_matching(&adj, cards.len()).is_perfect()
+    }
+
+    fn step(&mut self, rng: &mut impl Rng) {
+        let num_live = self.live.len();
+        if num_live < 2 {
+            return;
+        }
+        let a = self.live[rng.random_range(0..num_live)];
+        let b = self.live[rng.random_range(0..num_live)];
+        let (p, q) = (self.holder[a.num as usize], self.holder[b.num as usize]);
+        if p == q || !self.reachable[q].contains(a) || !self.reachable[p].contains(b) {
+            return;
+        }
+        let hand_p = (self.hands[p] - CardSet::from(a)) | CardSet::from(b);
+        let hand_q = (self.hands[q] - CardSet::from(b)) | CardSet::from(a);
+        if self.valid_hand(p, hand_p) && self.valid_hand(q, hand_q) {
+            self.hands[p] = hand_p;
+            self.hands[q] = hand_q;
+            self.holder[a.num as usize] = q;
+            self.holder[b.num as usize] = p;
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Engine {
     num_players: Rc<RefCell<usize>>,
-    num_cards: Rc<RefCell<usize>>,
+    deck: Rc<RefCell<&'static Deck>>,
     hand_map: Rc<RefCell<HashMap<usize, Hand>>>,
+    out_of_play: Rc<RefCell<CardSet>>,
+    queue: Rc<RefCell<VecDeque<Deduction>>>,
+    // The player whose private hand this engine knows, if any
+    seat: Rc<RefCell<Option<usize>>>,
+
+    // Every event applied so far and why each deduction was made
+    events: Rc<RefCell<Vec<Event>>>,
+    reasons: Rc<RefCell<HashMap<(usize, Card), Reason>>>,
+    book_reasons: Rc<RefCell<HashMap<(usize, Book), usize>>>,
+    // The state before each event, so events can be taken back
+    undo_stack: Rc<RefCell<Vec<Snapshot>>>,
+    // Cards whose part of the slot graph changed since it was last filtered
+    dirty: Rc<RefCell<CardSet>>,
 }
 
 impl Engine {
     pub fn init(g: &Fish) -> Self {
         let num_players = g.num_players();
-        let num_cards = g.num_cards();
+        let deck = g.deck();
+        let hand_sizes: Vec<usize> = g.players().iter().map(|p| p.cards.len()).collect();
+
+        // Any deal is consistent, so start the witness from the first one
+        let mut witness = deck.cards().iter();
         let hand_map = (0..num_players)
             .map(|i| {
+                let slots = (0..hand_sizes[i])
+                    .map(|_| SlotState {
+                        constraint: None,
+                        domain: deck.cards(),
+                        card: witness.next(),
+                    })
+                    .collect();
                 (
                     i,
                     Hand {
-                        slots: vec![None; num_cards / num_players],
-                        excluded_cards: HashSet::new(),
+                        slots,
+                        excluded_cards: CardSet::new(),
                     },
                 )
             })
             .collect();
 
         Engine {
-            num_cards: Rc::new(RefCell::new(num_cards)),
+            deck: Rc::new(RefCell::new(deck)),
             num_players: Rc::new(RefCell::new(num_players)),
             hand_map: Rc::new(RefCell::new(hand_map)),
+            out_of_play: Rc::new(RefCell::new(CardSet::new())),
+            queue: Rc::new(RefCell::new(VecDeque::new())),
+            seat: Rc::new(RefCell::new(None)),
+
+            events: Rc::new(RefCell::new(vec![])),
+            reasons: Rc::new(RefCell::new(HashMap::new())),
+            book_reasons: Rc::new(RefCell::new(HashMap::new())),
+            undo_stack: Rc::new(RefCell::new(vec![])),
+            dirty: Rc::new(RefCell::new(CardSet::new())),
         }
     }
 
     pub fn reset(&self, g: &Fish) {
-        let new_engine: Engine = Engine::init(g);
+        let seat = self.seat();
+        self.replace(Engine::init(g));
+        *self.seat.borrow_mut() = seat;
+        if let Some(seat) = seat {
+            self.register_hand(seat, g.get_hand(seat))
+                .expect("A fresh engine accepts any hand");
+        }
+    }
+
+    /// Take over the state of another engine
+    pub fn replace(&self, new_engine: Engine) {
         *self.num_players.borrow_mut() = new_engine.num_players.take();
-        *self.num_cards.borrow_mut() = new_engine.num_cards.take();
+        *self.deck.borrow_mut() = *new_engine.deck.borrow();
         *self.hand_map.borrow_mut() = new_engine.hand_map.take();
+        *self.out_of_play.borrow_mut() = new_engine.out_of_play.take();
+        *self.queue.borrow_mut() = new_engine.queue.take();
+        *self.events.borrow_mut() = new_engine.events.take();
+        *self.reasons.borrow_mut() = new_engine.reasons.take();
+        *self.book_reasons.borrow_mut() = new_engine.book_reasons.take();
+        *self.undo_stack.borrow_mut() = new_engine.undo_stack.take();
+        *self.dirty.borrow_mut() = new_engine.dirty.take();
+        *self.seat.borrow_mut() = new_engine.seat.take();
+    }
+
+    /// Engine that combines the public events with one seat's own hand
+    pub fn for_player(g: &Fish, seat: usize, hand: CardSet) -> Self {
+        let engine = Engine::init(g);
+        *engine.seat.borrow_mut() = Some(seat);
+        engine
+            .register_hand(seat, hand)
+            .expect("A fresh engine accepts any hand");
+        engine
+    }
+
+    pub fn register_hand(&self, player: usize, cards: CardSet) -> Result<(), ContradictionError> {
+        self.atomically(|| {
+            cards
+                .iter()
+                .for_each(|card| self.has_card(player, card, Reason::OwnHand));
+            self.propagate()
+        })
+        .map(|_| ())
     }
 
-    pub fn register_hand(&self, player: usize, cards: &[Card]) {
-        cards.iter().for_each(|card| self.has_card(player, *card));
+    /// Learn from a public event. If the event clashes with what is already
+    /// known, the engine is left untouched and the clash is returned
+    pub fn update_constraints(&self, event: &Event) -> Result<(), ContradictionError> {
+        let before = self
+            .atomically(|| {
+                self.events.borrow_mut().push(event.clone());
+                self.apply(event)?;
+                self.propagate()
+            })
+            .map_err(|err| ContradictionError {
+                event: Some(Box::new(event.clone())),
+                ..err
+            })?;
+        self.undo_stack.borrow_mut().push(before);
+        Ok(())
     }
 
-    pub fn update_constraints(&self, event: Event) {
-        match event {
+    /// Forget the last event and everything deduced from it, returning it
+    pub fn undo(&self) -> Option<Event> {
+        let before = self.undo_stack.borrow_mut().pop()?;
+        let event = self.events.borrow().last().cloned();
+        self.restore(before);
+        event
+    }
+
+    fn apply(&self, event: &Event) -> Result<(), ContradictionError> {
+        let n = self.num_events();
+        match *event {
             Event::Ask(Ask {
                 asker,
                 askee,
@@ -73,9 +313,9 @@ impl Engine {
                 outcome: AskOutcome::Success,
             }) => {
                 // Asker has 1 card of the book
-                self.has_book(asker, card.book());
-                self.remove_card(askee, card);
-                self.add_card(asker, card);
+                self.has_book(asker, self.book_of(card)?)?;
+                self.remove_card(askee, card)?;
+                self.add_card(asker, card)?;
             }
             Event::Ask(Ask {
                 asker,
@@ -85,150 +325,1135 @@ impl Engine {
             }) => {
                 // Asker has 1 card of the book
                 // Askee does not have the card
-                self.has_book(asker, card.book());
-                self.not_own_card(asker, card);
-                self.not_own_card(askee, card);
+                self.has_book(asker, self.book_of(card)?)?;
+                self.not_own_card(asker, card, Reason::Asked(n));
+                self.not_own_card(askee, card, Reason::Denied(n));
             }
-            Event::Declare(Declare { actual_cards, .. }) => {
+            Event::Declare(Declare {
+                book,
+                ref actual_cards,
+                ..
+            }) => {
                 for (player, cards) in actual_cards.iter() {
-                    for card in cards {
-                        self.remove_card(*player, *card);
+                    for card in cards.iter() {
+                        self.remove_card(*player, card)?;
                     }
                 }
+                self.retire_cards(book.mask());
+            }
+            Event::PassTurn(PassTurn { passer, .. }) => {
+                // Passer holds nothing, unless nobody is left to ask or they
+                // hold whole books only
+                let holds_nothing = {
+                    let mut hand_map = self.hand_map.borrow_mut();
+                    let opponents_out = hand_map
+                        .iter()
+                        .all(|(p, hand)| p % 2 == passer % 2 || hand.slots.is_empty());
+                    let smallest_book = self
+                        .deck()
+                        .books()
+                        .into_iter()
+                        .map(|book| book.mask().len())
+                        .min()
+                        .unwrap_or(0);
+                    let hand = Engine::hand_of(&mut hand_map, passer)?;
+                    if !hand.slots.is_empty() && !opponents_out && hand.slots.len() < smallest_book
+                    {
+                        return Err(Engine::clash(
+                            passer,
+                            hand,
+                            Contradiction::PassedWithCards(passer),
+                        ));
+                    }
+                    hand.slots.is_empty()
+                };
+                if holds_nothing {
+                    for card in self.live_cards().iter() {
+                        self.not_own_card(passer, card, Reason::Passed(n));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Run an update, restoring the previous state if it hits a contradiction.
+    // Otherwise the previous state is returned for a later undo
+    fn atomically(
+        &self,
+        update: impl FnOnce() -> Result<(), ContradictionError>,
+    ) -> Result<Snapshot, ContradictionError> {
+        let before = self.snapshot();
+        match update() {
+            Ok(()) => Ok(before),
+            Err(err) => {
+                self.restore(before);
+                Err(err)
             }
         }
     }
 
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            hand_map: self.hand_map.borrow().clone(),
+            out_of_play: *self.out_of_play.borrow(),
+            num_events: self.num_events(),
+            reasons: self.reasons.borrow().clone(),
+            book_reasons: self.book_reasons.borrow().clone(),
+        }
+    }
+
+    fn restore(&self, snapshot: Snapshot) {
+        *self.hand_map.borrow_mut() = snapshot.hand_map;
+        *self.out_of_play.borrow_mut() = snapshot.out_of_play;
+        self.events.borrow_mut().truncate(snapshot.num_events);
+        *self.reasons.borrow_mut() = snapshot.reasons;
+        *self.book_reasons.borrow_mut() = snapshot.book_reasons;
+        self.queue.borrow_mut().clear();
+        // Snapshots are taken between updates, once everything was filtered
+        *self.dirty.borrow_mut() = CardSet::new();
+    }
+
+    /// Prove that at least one legal deal exists, rebuilding every domain
+    /// from the raw constraints rather than trusting the cached ones
+    pub fn check_consistency(&self) -> Result<(), ContradictionError> {
+        let live = self.live_cards().to_vec();
+
+        let hand_map = self.hand_map.borrow();
+        let mut positions = vec![];
+        let mut adj = vec![];
+        for player in 0..self.num_players() {
+            let hand = &hand_map[&player];
+            for slot in hand.slots.iter() {
+                positions.push((player, slot.constraint.clone()));
+                adj.push(
+                    (0..live.len())
+                        .filter(|v| match slot.constraint {
+                            Some(Constraint::IsCard(c)) => c == live[*v],
+                            Some(Constraint::InBook(b)) => {
+                                b.mask().contains(live[*v])
+                                    && !hand.excluded_cards.contains(live[*v])
+                            }
+                            None => !hand.excluded_cards.contains(live[*v]),
+                        })
+                        .collect::<Vec<usize>>(),
+                );
+            }
+        }
+
+        let matching = max_matching(&adj, live.len());
+        if matching.is_perfect() {
+            return Ok(());
+        }
+        Err(ContradictionError {
+            event: None,
+            kind: Contradiction::NoDeal,
+            constraints: positions
+                .into_iter()
+                .zip(matching.left.iter())
+                .filter(|(_, card)| card.is_none())
+                .map(|(position, _)| position)
+                .collect(),
+        })
+    }
+
     /// Player owns book. Update a None constraint if player does not already
     /// have a card of that book or hold the OwnBook constraint
-    pub fn has_book(&self, player: usize, book: Book) {
+    pub fn has_book(&self, player: usize, book: Book) -> Result<(), ContradictionError> {
         let mut hand_map = self.hand_map.borrow_mut();
 
-        let hand = hand_map.get_mut(&player).unwrap();
-        hand.slots.sort_by_key(|slot| match slot {
-            Some(Constraint::IsCard(_)) => 0,
-            Some(Constraint::InBook(_)) => 1,
-            None => 2,
-        });
+        let hand = Engine::hand_of(&mut hand_map, player)?;
+        Engine::sort_slots(hand);
+        self.book_reasons
+            .borrow_mut()
+            .entry((player, book))
+            .or_insert(self.num_events());
 
         for slot in hand.slots.iter_mut() {
-            match slot {
-                Some(Constraint::IsCard(c)) if book == c.book() => return,
-                Some(Constraint::InBook(b)) if book == *b => return,
+            match slot.constraint {
+                Some(Constraint::IsCard(c)) if book.mask().contains(c) => return Ok(()),
+                Some(Constraint::InBook(b)) if book == b => return Ok(()),
                 None => {
-                    *slot = Some(Constraint::InBook(book));
-                    return;
+                    slot.constraint = Some(Constraint::InBook(book));
+                    self.narrow(slot, slot.domain & book.mask());
+                    if let Some(card) = Engine::singleton(slot) {
+                        self.queue.borrow_mut().push_back(Deduction::Holds(
+                            player,
+                            card,
+                            Reason::LastOfBook(book),
+                        ));
+                    }
+                    return Ok(());
                 }
                 _ => continue,
             }
         }
-        panic!("No slot available to add book constraint");
+        Err(Engine::clash(
+            player,
+            hand,
+            Contradiction::MissingBook(player, book),
+        ))
     }
 
-    /// Player has a card. Update constraints if there are any
-    pub fn has_card(&self, player: usize, card: Card) {
-        let mut hand_map = self.hand_map.borrow_mut();
-
-        for (id, hand) in hand_map.iter_mut() {
-            if *id == player {
-                hand.slots.sort_by_key(|slot| match slot {
-                    Some(Constraint::IsCard(_)) => 0,
-                    Some(Constraint::InBook(_)) => 1,
-                    None => 2,
-                });
-                if let Some(idx) = hand.slots.iter().position(|slot| match slot {
-                    Some(Constraint::IsCard(c)) if *c == card => true,
-                    Some(Constraint::InBook(b)) if *b == card.book() => true,
-                    None => true,
-                    _ => false,
-                }) {
-                    hand.slots[idx] = Some(Constraint::IsCard(card))
-                } else {
-                    panic!("No slot available to add card constraint");
-                }
-            } else {
-                hand.excluded_cards.insert(card);
-            }
-        }
+    /// Player has a card. Queued until the next propagation
+    pub fn has_card(&self, player: usize, card: Card, reason: Reason) {
+        self.queue
+            .borrow_mut()
+            .push_back(Deduction::Holds(player, card, reason));
     }
 
     /// Add a card to one of the player's slots
     /// And add it to the excluded cards of all other players
-    pub fn add_card(&self, player: usize, card: Card) {
+    pub fn add_card(&self, player: usize, card: Card) -> Result<(), ContradictionError> {
+        let n = self.num_events();
+        Engine::hand_of(&mut self.hand_map.borrow_mut(), player)?;
+        {
+            // Everything known about where the card was no longer applies
+            let mut reasons = self.reasons.borrow_mut();
+            reasons.retain(|(_, c), _| *c != card);
+            reasons.insert((player, card), Reason::Received(n));
+        }
+
         let mut hand_map = self.hand_map.borrow_mut();
-        for (id, hand) in hand_map.iter_mut() {
-            if *id == player {
+        let mut queue = self.queue.borrow_mut();
+        for id in 0..self.num_players() {
+            let hand = hand_map.get_mut(&id).unwrap();
+            if id == player {
                 hand.excluded_cards.insert(card);
-                hand.slots.push(Some(Constraint::IsCard(card)));
+                for slot in hand.slots.iter_mut() {
+                    if self.narrow(slot, slot.domain - CardSet::from(card)) {
+                        if let Some(only) = Engine::singleton(slot) {
+                            queue.push_back(Deduction::Holds(
+                                player,
+                                only,
+                                Engine::singleton_reason(slot, n),
+                            ));
+                        }
+                    }
+                }
+                hand.slots.push(SlotState {
+                    constraint: Some(Constraint::IsCard(card)),
+                    domain: CardSet::from(card),
+                    card: None,
+                });
+                self.dirty.borrow_mut().insert(card);
             } else {
-                hand.excluded_cards.insert(card);
+                queue.push_back(Deduction::Lacks(id, card, Reason::HeldBy(player)));
             }
         }
+        Ok(())
     }
 
     /// Player no longer owns a card. Remove the first OwnCard constraint,
-    /// OwnBook constraint, or a None constraint in that order
-    pub fn remove_card(&self, player: usize, card: Card) {
+    /// OwnBook constraint, or a None constraint in that order, from the
+    /// slots that could still hold the card
+    pub fn remove_card(&self, player: usize, card: Card) -> Result<(), ContradictionError> {
         let mut hand_map = self.hand_map.borrow_mut();
-        let hand = hand_map.get_mut(&player).unwrap();
-        hand.slots.sort_by_key(|slot| match slot {
-            Some(Constraint::IsCard(_)) => 0,
-            Some(Constraint::InBook(_)) => 1,
-            None => 2,
-        });
+        let hand = Engine::hand_of(&mut hand_map, player)?;
+        Engine::sort_slots(hand);
 
-        if let Some(idx) = hand.slots.iter().position(|slot| match slot {
-            Some(Constraint::IsCard(c)) if *c == card => true,
-            Some(Constraint::InBook(b)) if *b == card.book() => true,
-            None => true,
-            _ => false,
+        if let Some(idx) = hand.slots.iter().position(|slot| match slot.constraint {
+            Some(Constraint::IsCard(c)) => c == card,
+            Some(Constraint::InBook(_)) | None => slot.domain.contains(card),
         }) {
-            hand.slots.remove(idx);
+            let slot = hand.slots.remove(idx);
+            *self.dirty.borrow_mut() |= slot.domain;
+            Ok(())
+        } else if hand.excluded_cards.contains(card) {
+            Err(Engine::clash(
+                player,
+                hand,
+                Contradiction::ExcludedCard(player, card),
+            ))
         } else {
-            panic!("No slot available to remove");
+            Err(Engine::clash(
+                player,
+                hand,
+                Contradiction::MissingCard(player, card),
+            ))
         }
     }
 
-    /// Players do not own the card
-    pub fn not_own_card(&self, player: usize, card: Card) {
-        let mut hand_map = self.hand_map.borrow_mut();
-        let hand = hand_map.get_mut(&player).unwrap();
-        hand.excluded_cards.insert(card);
+    /// Players do not own the card. Queued until the next propagation
+    pub fn not_own_card(&self, player: usize, card: Card, reason: Reason) {
+        self.queue
+            .borrow_mut()
+            .push_back(Deduction::Lacks(player, card, reason));
     }
 
-    // TODO: naive prune, need to see if incremental prune is possible
-    pub fn prune(&self) -> HashMap<usize, Vec<Vec<Card>>> {
-        let mut output = HashMap::new();
-        let all_cards: HashSet<Card> = { 0..54 }.map(|n| Card { num: n }).collect();
+    /// Cards of a declared book leave the game
+    fn retire_cards(&self, cards: CardSet) {
+        *self.out_of_play.borrow_mut() |= cards;
+        for hand in self.hand_map.borrow_mut().values_mut() {
+            for slot in hand.slots.iter_mut() {
+                self.narrow(slot, slot.domain - cards);
+            }
+        }
+    }
+
+    /// Cards each slot holds in at least one consistent deal
+    pub fn domains(&self) -> HashMap<usize, Vec<CardSet>> {
+        self.hand_map
+            .borrow()
+            .iter()
+            .map(|(player, hand)| (*player, hand.slots.iter().map(|slot| slot.domain).collect()))
+            .collect()
+    }
+
+    /// Cards a single slot holds in at least one consistent deal
+    pub fn domain(&self, player: usize, slot: usize) -> CardSet {
+        self.hand_map.borrow()[&player].slots[slot].domain
+    }
+
+    /// Cards that have not been declared yet
+    pub fn live_cards(&self) -> CardSet {
+        self.deck().cards() - *self.out_of_play.borrow()
+    }
+
+    pub fn deck(&self) -> &'static Deck {
+        *self.deck.borrow()
+    }
+
+    // The book of a card named in an event, which must be part of the deck
+    fn book_of(&self, card: Card) -> Result<Book, ContradictionError> {
+        self.deck().book_of(card).ok_or(ContradictionError {
+            event: None,
+            kind: Contradiction::NotInDeck(card),
+            constraints: vec![],
+        })
+    }
+
+    /// Push queued deductions through the hands until nothing new follows.
+    /// Local rules run first: a pinned card leaves every other hand, a card
+    /// left in one hand is pinned there, and a slot with one candidate is
+    /// pinned to it. The witness deal is then repaired where the new facts
+    /// broke it, and the matching filter turns any remaining counting
+    /// argument into further deductions. Both only revisit the parts of the
+    /// slot graph the new facts touched
+    fn propagate(&self) -> Result<(), ContradictionError> {
+        loop {
+            loop {
+                let next = self.queue.borrow_mut().pop_front();
+                match next {
+                    Some(Deduction::Holds(player, card, reason)) => {
+                        self.pin(player, card, reason)?
+                    }
+                    Some(Deduction::Lacks(player, card, reason)) => {
+                        self.exclude(player, card, reason)?
+                    }
+                    None => break,
+                }
+            }
+
+            if !self.filter()? {
+                return Ok(());
+            }
+        }
+    }
 
+    fn pin(&self, player: usize, card: Card, reason: Reason) -> Result<(), ContradictionError> {
+        {
+            let mut hand_map = self.hand_map.borrow_mut();
+            let hand = Engine::hand_of(&mut hand_map, player)?;
+            if hand
+                .slots
+                .iter()
+                .any(|slot| slot.constraint == Some(Constraint::IsCard(card)))
+            {
+                self.reasons
+                    .borrow_mut()
+                    .entry((player, card))
+                    .or_insert(reason);
+                return Ok(());
+            }
+
+            // Any hand holding the card can place it in a slot of its book
+            // before falling back to an unconstrained slot
+            Engine::sort_slots(hand);
+            let Some(slot) = hand.slots.iter_mut().find(|slot| {
+                slot.domain.contains(card)
+                    && match slot.constraint {
+                        Some(Constraint::InBook(b)) => b.mask().contains(card),
+                        Some(Constraint::IsCard(_)) => false,
+                        None => true,
+                    }
+            }) else {
+                return Err(Engine::clash(
+                    player,
+                    hand,
+                    Contradiction::MissingCard(player, card),
+                ));
+            };
+            slot.constraint = Some(Constraint::IsCard(card));
+            self.narrow(slot, CardSet::from(card));
+        }
+        self.reasons.borrow_mut().insert((player, card), reason);
+
+        let mut queue = self.queue.borrow_mut();
+        for other in (0..self.num_players()).filter(|p| *p != player) {
+            queue.push_back(Deduction::Lacks(other, card, Reason::HeldBy(player)));
+        }
+        Ok(())
+    }
+
+    fn exclude(&self, player: usize, card: Card, reason: Reason) -> Result<(), ContradictionError> {
+        let n = self.num_events();
+        let mut changed = false;
+        {
+            let mut hand_map = self.hand_map.borrow_mut();
+            let hand = Engine::hand_of(&mut hand_map, player)?;
+            if hand
+                .slots
+                .iter()
+                .any(|slot| slot.constraint == Some(Constraint::IsCard(card)))
+            {
+                return Err(Engine::clash(
+                    player,
+                    hand,
+                    Contradiction::ExcludedCard(player, card),
+                ));
+            }
+
+            changed |= hand.excluded_cards.insert(card);
+            for slot in hand.slots.iter_mut() {
+                if self.narrow(slot, slot.domain - CardSet::from(card)) {
+                    changed = true;
+                    if let Some(only) = Engine::singleton(slot) {
+                        self.queue.borrow_mut().push_back(Deduction::Holds(
+                            player,
+                            only,
+                            Engine::singleton_reason(slot, n),
+                        ));
+                    }
+                }
+            }
+        }
+
+        {
+            let mut reasons = self.reasons.borrow_mut();
+            if changed || !reasons.contains_key(&(player, card)) {
+                reasons.insert((player, card), reason);
+            }
+        }
+
+        if changed && !self.out_of_play.borrow().contains(card) {
+            if let [holder] = self.holders(card)[..] {
+                self.queue.borrow_mut().push_back(Deduction::Holds(
+                    holder,
+                    card,
+                    Reason::OnlyCandidate,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Players who hold the card in at least one consistent deal
+    pub fn holders(&self, card: Card) -> Vec<usize> {
         let hand_map = self.hand_map.borrow();
-        for (player, hand) in hand_map.iter() {
-            output.insert(
-                *player,
-                hand.slots
+        let mut holders: Vec<usize> = hand_map
+            .iter()
+            .filter(|(_, hand)| hand.slots.iter().any(|slot| slot.domain.contains(card)))
+            .map(|(player, _)| *player)
+            .collect();
+        holders.sort();
+        holders
+    }
+
+    /// Books none of whose cards have been declared
+    pub fn books_in_play(&self) -> Vec<Book> {
+        let live = self.live_cards();
+        self.deck()
+            .books()
+            .into_iter()
+            .filter(|book| book.mask().is_subset(live))
+            .collect()
+    }
+
+    /// Where every card of the book is, if each one is proven to sit with a
+    /// single player of the team. Teams alternate seats, so team `t` is
+    /// every player seated at `t`, `t + 2`, ...
+    pub fn located_within(&self, book: Book, team: usize) -> Option<Assignment> {
+        if !book.mask().is_subset(self.live_cards()) {
+            return None;
+        }
+        let mut assignment = Assignment::new();
+        for card in book.mask() {
+            let [holder] = self.holders(card)[..] else {
+                return None;
+            };
+            if holder % 2 != team {
+                return None;
+            }
+            assignment.entry(holder).or_default().insert(card);
+        }
+        Some(assignment)
+    }
+
+    /// Whether every card of the book can still be with the team
+    pub fn may_be_within(&self, book: Book, team: usize) -> bool {
+        book.mask().is_subset(self.live_cards())
+            && book
+                .mask()
+                .iter()
+                .all(|card| self.holders(card).iter().any(|holder| holder % 2 == team))
+    }
+
+    /// The most common placement of the book within the team across the
+    /// deals, with the share of deals that place it exactly so
+    pub fn likely_within(
+        &self,
+        book: Book,
+        team: usize,
+        deals: &[Deal],
+    ) -> Option<(Assignment, f32)> {
+        let mut counts: BTreeMap<Vec<(usize, CardSet)>, usize> = BTreeMap::new();
+        for deal in deals.iter() {
+            let mut placement: Vec<(usize, CardSet)> = deal
+                .iter()
+                .map(|(player, cards)| (*player, *cards & book.mask()))
+                .filter(|(_, cards)| !cards.is_empty())
+                .collect();
+            if placement.iter().all(|(player, _)| player % 2 == team) {
+                placement.sort_by_key(|(player, _)| *player);
+                *counts.entry(placement).or_default() += 1;
+            }
+        }
+        counts
+            .into_iter()
+            .max_by_key(|(_, count)| *count)
+            .map(|(placement, count)| {
+                (
+                    placement.into_iter().collect(),
+                    count as f32 / deals.len() as f32,
+                )
+            })
+    }
+
+    /// Repair the witness deal, then remove every (slot, card) pair that
+    /// belongs to no consistent deal and queue what that reveals. Only the
+    /// components of the slot graph around dirty cards are looked at, the
+    /// others have not changed since they were last filtered. Returns
+    /// whether anything was removed or queued
+    fn filter(&self) -> Result<bool, ContradictionError> {
+        let n = self.num_events();
+        let (graph, mut matching) = self.slot_graph_around(self.dirty.take());
+        repair(&graph.adj, &mut matching);
+        if !matching.is_perfect() {
+            let hand_map = self.hand_map.borrow();
+            return Err(ContradictionError {
+                event: None,
+                kind: Contradiction::NoDeal,
+                constraints: graph
+                    .positions
                     .iter()
-                    .map(|slot| match slot {
-                        Some(Constraint::IsCard(card)) => vec![*card],
-                        Some(Constraint::InBook(book)) => book
-                            .cards()
-                            .into_iter()
-                            .filter(|c| !hand.excluded_cards.contains(c))
-                            .collect(),
-                        None => all_cards
-                            .clone()
-                            .into_iter()
-                            .filter(|c| !hand.excluded_cards.contains(c))
-                            .collect(),
+                    .zip(matching.left.iter())
+                    .filter(|(_, card)| card.is_none())
+                    .map(|((player, idx), _)| {
+                        (*player, hand_map[player].slots[*idx].constraint.clone())
                     })
                     .collect(),
-            );
+            });
+        }
+        {
+            let mut hand_map = self.hand_map.borrow_mut();
+            for (u, (player, idx)) in graph.positions.iter().enumerate() {
+                let card = matching.left[u].map(|v| graph.live[v]);
+                hand_map.get_mut(player).unwrap().slots[*idx].card = card;
+            }
+        }
+
+        let feasible = feasible_edges(&graph.adj, &matching);
+
+        let mut removed = false;
+        {
+            let mut hand_map = self.hand_map.borrow_mut();
+            for (u, (player, idx)) in graph.positions.iter().enumerate() {
+                if feasible[u].len() == graph.adj[u].len() {
+                    continue;
+                }
+                let slot = &mut hand_map.get_mut(player).unwrap().slots[*idx];
+                for v in graph.adj[u].iter().filter(|v| !feasible[u].contains(v)) {
+                    slot.domain.remove(graph.live[*v]);
+                }
+                if let Some(card) = Engine::singleton(slot) {
+                    self.queue.borrow_mut().push_back(Deduction::Holds(
+                        *player,
+                        card,
+                        Reason::Counting(n),
+                    ));
+                }
+                removed = true;
+            }
+        }
+
+        // A card that left every slot of a hand is excluded from it. This also
+        // catches hands that filled up as slots were pinned or given away
+        let hand_map = self.hand_map.borrow();
+        let mut queue = self.queue.borrow_mut();
+        let live = self.live_cards();
+        for player in 0..self.num_players() {
+            let hand = &hand_map[&player];
+            let reachable = hand
+                .slots
+                .iter()
+                .fold(CardSet::new(), |cards, slot| cards | slot.domain);
+            for card in live - reachable - hand.excluded_cards {
+                queue.push_back(Deduction::Lacks(player, card, Reason::Counting(n)));
+            }
+        }
+        Ok(removed || !queue.is_empty())
+    }
+
+    /// Why the engine believes what it does about the player and the card,
+    /// traced back through the deductions to the events that started them
+    pub fn explain(&self, card: Card, player: usize) -> Explanation {
+        self.explain_fact(card, player, &mut HashSet::new())
+    }
+
+    fn explain_fact(
+        &self,
+        card: Card,
+        player: usize,
+        seen: &mut HashSet<(usize, Card)>,
+    ) -> Explanation {
+        if self.out_of_play.borrow().contains(card) {
+            let declared = self.events.borrow().iter().position(|event| {
+                matches!(event, Event::Declare(Declare { book, .. }) if book.mask().contains(card))
+            });
+            return Explanation {
+                fact: Fact::Declared(card),
+                reason: declared.map(|idx| Reason::Declared(idx + 1)),
+                premises: vec![],
+            };
+        }
+
+        let pinned = self.hand_map.borrow()[&player]
+            .slots
+            .iter()
+            .any(|slot| slot.constraint == Some(Constraint::IsCard(card)));
+        let fact = if pinned {
+            Fact::Holds(player, card)
+        } else if !self.holders(card).contains(&player) {
+            Fact::Lacks(player, card)
+        } else {
+            Fact::Unknown(player, card)
+        };
+        let reason = match fact {
+            Fact::Unknown(..) => None,
+            _ => self.reasons.borrow().get(&(player, card)).cloned(),
+        };
+
+        let mut premises = vec![];
+        if seen.insert((player, card)) {
+            match reason {
+                Some(Reason::HeldBy(holder)) => {
+                    premises.push(self.explain_fact(card, holder, seen));
+                }
+                Some(Reason::OnlyCandidate) => {
+                    for other in (0..self.num_players()).filter(|p| *p != player) {
+                        premises.push(self.explain_fact(card, other, seen));
+                    }
+                }
+                Some(Reason::LastOfBook(book)) => {
+                    premises.push(Explanation {
+                        fact: Fact::HasBook(player, book),
+                        reason: self
+                            .book_reasons
+                            .borrow()
+                            .get(&(player, book))
+                            .map(|n| Reason::ShowedBook(*n)),
+                        premises: vec![],
+                    });
+                    for other in book.mask().iter().filter(|c| *c != card) {
+                        premises.push(self.explain_fact(other, player, seen));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Explanation {
+            fact,
+            reason,
+            premises,
+        }
+    }
+
+    /// The event with the given number, counting from 1
+    pub fn event(&self, n: usize) -> Option<Event> {
+        self.events.borrow().get(n.wrapping_sub(1)).cloned()
+    }
+
+    fn num_events(&self) -> usize {
+        self.events.borrow().len()
+    }
+
+    /// Flatten the cached domains and witness into a bipartite graph
+    fn slot_graph(&self) -> (SlotGraph, Matching) {
+        self.slot_graph_around(self.live_cards())
+    }
+
+    // The part of the slot graph connected to any of the cards through the
+    // slot domains, with the witness deal on it. Slots that can hold nothing
+    // are always part of it, as no deal exists while they do
+    fn slot_graph_around(&self, cards: CardSet) -> (SlotGraph, Matching) {
+        let hand_map = self.hand_map.borrow();
+        let mut slots: Vec<(usize, usize, &SlotState)> = vec![];
+        for player in 0..self.num_players() {
+            for (idx, slot) in hand_map[&player].slots.iter().enumerate() {
+                slots.push((player, idx, slot));
+            }
+        }
+        let mut region = cards & self.live_cards();
+        let mut included: Vec<bool> = slots
+            .iter()
+            .map(|(_, _, slot)| slot.domain.is_empty())
+            .collect();
+        loop {
+            let mut grown = false;
+            for (i, (_, _, slot)) in slots.iter().enumerate() {
+                if !included[i] && slot.domain.intersects(region) {
+                    included[i] = true;
+                    region |= slot.domain;
+                    grown = true;
+                }
+            }
+            if !grown {
+                break;
+            }
+        }
+
+        let live = region.to_vec();
+        let mut index = [0; 64];
+        for (i, card) in live.iter().enumerate() {
+            index[card.num as usize] = i;
+        }
+        let mut positions = vec![];
+        let mut adj = vec![];
+        let mut matching = Matching {
+            left: vec![],
+            right: vec![None; live.len()],
+        };
+        for (i, (player, idx, slot)) in slots.into_iter().enumerate() {
+            if included[i] {
+                let cards: Vec<usize> = slot.domain.iter().map(|c| index[c.num as usize]).collect();
+
+                // The witness card may have been claimed by another slot
+                let matched = slot
+                    .card
+                    .map(|c| index[c.num as usize])
+                    .filter(|v| matching.right[*v].is_none());
+                if let Some(v) = matched {
+                    matching.right[v] = Some(adj.len());
+                }
+                matching.left.push(matched);
+
+                positions.push((player, idx));
+                adj.push(cards);
+            }
+        }
+        (
+            SlotGraph {
+                live,
+                positions,
+                adj,
+            },
+            matching,
+        )
+    }
+
+    /// Draw deals consistent with the constraints, approximately uniformly.
+    /// See `deal_sampler`
+    pub fn sample_deals(&self, num_samples: usize, rng: &mut impl Rng) -> Vec<Deal> {
+        let mut sampler = self.deal_sampler(rng);
+        (0..num_samples).map(|_| sampler.next_deal(rng)).collect()
+    }
+
+    /// A Markov chain over the deals consistent with the constraints. Starts
+    /// from the solver's witness and swaps two cards between hands whenever
+    /// both hands stay valid, burning in before it is returned. Swapping
+    /// pairs cannot always reach every deal: if three players may each hold
+    /// only two of three cards in a ring, no single swap turns the ring. So
+    /// the deals drawn are only approximately uniform
+    pub fn deal_sampler(&self, rng: &mut impl Rng) -> DealSampler {
+        let (graph, matching) = self.slot_graph();
+        let num_players = self.num_players();
+
+        let mut player_slots: Vec<Vec<CardSet>> = vec![vec![]; num_players];
+        let mut reachable = vec![CardSet::new(); num_players];
+        {
+            let hand_map = self.hand_map.borrow();
+            for (player, idx) in graph.positions.iter() {
+                let domain = hand_map[player].slots[*idx].domain;
+                player_slots[*player].push(domain);
+                reachable[*player] |= domain;
+            }
+        }
+
+        let mut hands = vec![CardSet::new(); num_players];
+        let mut holder = [0; 64];
+        for (slot, card) in matching.left.iter().enumerate() {
+            let card = graph.live[card.unwrap()];
+            let player = graph.positions[slot].0;
+            hands[player].insert(card);
+            holder[card.num as usize] = player;
+        }
+
+        let mut sampler = DealSampler {
+            live: graph.live,
+            player_slots,
+            reachable,
+            hands,
+            holder,
+        };
+        for _ in 0..BURN_IN_STEPS * sampler.live.len() {
+            sampler.step(rng);
+        }
+        sampler
+    }
+
+    /// For every card in play, the probability that each player holds it,
+    /// estimated over `num_samples` consistent deals
+    pub fn probabilities(&self, num_samples: usize, rng: &mut impl Rng) -> HashMap<Card, ProbDist> {
+        let mut output: HashMap<Card, ProbDist> = HashMap::new();
+        let deals = self.sample_deals(num_samples, rng);
+        for deal in deals.iter() {
+            for (player, cards) in deal.iter() {
+                for card in cards.iter() {
+                    *output.entry(card).or_default().entry(*player).or_default() +=
+                        1.0 / deals.len() as f32;
+                }
+            }
         }
         output
     }
 
     pub fn num_players(&self) -> usize {
-        return *self.num_players.borrow();
+        *self.num_players.borrow()
+    }
+
+    pub fn seat(&self) -> Option<usize> {
+        *self.seat.borrow()
+    }
+
+    // Helpers
+    fn sort_slots(hand: &mut Hand) {
+        hand.slots.sort_by_key(|slot| match slot.constraint {
+            Some(Constraint::IsCard(_)) => 0,
+            Some(Constraint::InBook(_)) => 1,
+            None => 2,
+        });
+    }
+
+    // The hand of a seat named by an event, which must be at the table
+    fn hand_of(
+        hand_map: &mut HashMap<usize, Hand>,
+        player: usize,
+    ) -> Result<&mut Hand, ContradictionError> {
+        hand_map.get_mut(&player).ok_or(ContradictionError {
+            event: None,
+            kind: Contradiction::NoSuchPlayer(player),
+            constraints: vec![],
+        })
+    }
+
+    fn singleton(slot: &SlotState) -> Option<Card> {
+        match (slot.domain.len(), &slot.constraint) {
+            (1, Some(Constraint::IsCard(_))) => None,
+            (1, _) => slot.domain.first(),
+            _ => None,
+        }
+    }
+
+    fn clash(player: usize, hand: &Hand, kind: Contradiction) -> ContradictionError {
+        ContradictionError {
+            event: None,
+            kind,
+            constraints: hand
+                .slots
+                .iter()
+                .map(|slot| (player, slot.constraint.clone()))
+                .collect(),
+        }
+    }
+
+    fn singleton_reason(slot: &SlotState, n: usize) -> Reason {
+        match slot.constraint {
+            Some(Constraint::InBook(book)) => Reason::LastOfBook(book),
+            _ => Reason::Counting(n),
+        }
+    }
+
+    // Shrink a slot's domain, noting the part of the slot graph it touched.
+    // Returns whether anything was removed
+    fn narrow(&self, slot: &mut SlotState, domain: CardSet) -> bool {
+        if domain == slot.domain {
+            return false;
+        }
+        *self.dirty.borrow_mut() |= slot.domain;
+        slot.domain = domain;
+        Engine::drop_stale_card(slot);
+        true
+    }
+
+    fn drop_stale_card(slot: &mut SlotState) {
+        if slot.card.is_some_and(|c| !slot.domain.contains(c)) {
+            slot.card = None;
+        }
     }
 }
 
 pub type Slot = Option<Constraint>;
+
+/// What the engine could not reconcile
+#[derive(Clone, Debug)]
+pub enum Contradiction {
+    /// No free slot is left to hold a card of the book
+    MissingBook(usize, Book),
+    /// No slot can hold, or give up, the card
+    MissingCard(usize, Card),
+    /// The card was ruled out for a hand already pinned to it
+    ExcludedCard(usize, Card),
+    /// The player passed the turn while they could still ask for a card
+    PassedWithCards(usize),
+    /// The card is not dealt in this deck
+    NotInDeck(Card),
+    /// The seat is not at the table
+    NoSuchPlayer(usize),
+    /// No deal satisfies every hand at once
+    NoDeal,
+}
+
+#[derive(Clone, Debug)]
+pub struct ContradictionError {
+    /// The event being applied when the clash surfaced
+    pub event: Option<Box<Event>>,
+    pub kind: Contradiction,
+    /// The constraints that could not be satisfied, with the player holding them
+    pub constraints: Vec<(usize, Slot)>,
+}
+
+impl std::error::Error for ContradictionError {}
+
+impl std::fmt::Display for ContradictionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.event {
+            Some(event) => write!(f, "{event:?} contradicts the constraints: ")?,
+            None => write!(f, "Contradiction: ")?,
+        }
+        match self.kind {
+            Contradiction::MissingBook(player, book) => {
+                write!(f, "Player {player} has no slot left for a card of {book:?}")?
+            }
+            Contradiction::MissingCard(player, card) => {
+                write!(f, "Player {player} has no slot that can hold {card}")?
+            }
+            Contradiction::ExcludedCard(player, card) => {
+                write!(f, "Player {player} holds {card} but was ruled out from it")?
+            }
+            Contradiction::PassedWithCards(player) => {
+                write!(f, "Player {player} passed the turn but could still ask")?
+            }
+            Contradiction::NotInDeck(card) => write!(f, "{card} is not part of the deck")?,
+            Contradiction::NoSuchPlayer(player) => write!(f, "there is no player {player}")?,
+            Contradiction::NoDeal => write!(f, "no deal satisfies every hand")?,
+        }
+        for (player, slot) in self.constraints.iter() {
+            match slot {
+                Some(Constraint::IsCard(card)) => write!(f, "\n  Player {player}: {card}")?,
+                Some(Constraint::InBook(book)) => write!(f, "\n  Player {player}: {book:?}")?,
+                None => write!(f, "\n  Player {player}: None")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::game::tests::{human_table, play_at_random};
+    use crate::rules::Rules;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn ask(asker: usize, askee: usize, card: &str, outcome: AskOutcome) -> Event {
+        Event::Ask(Ask {
+            asker,
+            askee,
+            card: card.parse().unwrap(),
+            outcome,
+        })
+    }
+
+    fn public_engine() -> Engine {
+        Engine::init(&Fish::init(&Rules::default(), 0, 1))
+    }
+
+    // Whether each player's cards can fill their slots one to one
+    fn fits(e: &Engine, hands: &[CardSet]) -> bool {
+        e.domains().iter().all(|(player, domains)| {
+            let cards = hands[*player].to_vec();
+            let adj: Vec<Vec<usize>> = domains
+                .iter()
+                .map(|domain| {
+                    (0..cards.len())
+                        .filter(|k| domain.contains(cards[*k]))
+                        .collect()
+                })
+                .collect();
+            domains.len() == cards.len() && max_matching(&adj, cards.len()).is_perfect()
+        })
+    }
+
+    /// What the engine knows, leaving out the witness deal
+    pub(crate) fn knowledge(e: &Engine) -> Vec<String> {
+        let hand_map = e.hand_map.borrow();
+        let mut knowledge: Vec<String> = (0..e.num_players())
+            .map(|p| {
+                let hand = &hand_map[&p];
+                let slots: Vec<_> = hand
+                    .slots
+                    .iter()
+                    .map(|slot| (&slot.constraint, slot.domain))
+                    .collect();
+                format!("{:?} {:?}", slots, hand.excluded_cards)
+            })
+            .collect();
+        let mut reasons: Vec<String> = e
+            .reasons
+            .borrow()
+            .iter()
+            .map(|fact| format!("{fact:?}"))
+            .chain(
+                e.book_reasons
+                    .borrow()
+                    .iter()
+                    .map(|fact| format!("{fact:?}")),
+            )
+            .collect();
+        reasons.sort();
+        knowledge.extend(reasons);
+        knowledge.push(format!("{:?} {}", e.out_of_play.borrow(), e.num_events()));
+        knowledge
+    }
+
+    fn hands(g: &Fish) -> Vec<CardSet> {
+        (0..g.num_players()).map(|p| g.get_hand(p)).collect()
+    }
+
+    #[test]
+    fn true_hands_fit_every_engine() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let g = human_table(6, 1);
+        let public = Engine::init(&g);
+        while !g.is_over() {
+            let event = play_at_random(&g, &mut rng);
+            public.update_constraints(&event).unwrap();
+            let hands = hands(&g);
+            assert!(fits(&public, &hands));
+            for seat in 0..g.num_players() {
+                assert!(fits(&g.perspective(seat), &hands));
+            }
+        }
+    }
+
+    // Whether the engine has drawn every conclusion a pass over the whole
+    // slot graph would: each card left in a domain is held by that slot in
+    // some deal, and a card no slot of a hand can hold is excluded from it
+    fn fully_filtered(e: &Engine) -> bool {
+        let (graph, mut matching) = e.slot_graph();
+        repair(&graph.adj, &mut matching);
+        let feasible = feasible_edges(&graph.adj, &matching);
+        let hand_map = e.hand_map.borrow();
+        matching.is_perfect()
+            && (0..graph.adj.len()).all(|u| feasible[u].len() == graph.adj[u].len())
+            && hand_map.values().all(|hand| {
+                let reachable = hand
+                    .slots
+                    .iter()
+                    .fold(CardSet::new(), |cards, slot| cards | slot.domain);
+                (e.live_cards() - reachable).is_subset(hand.excluded_cards)
+            })
+    }
+
+    #[test]
+    fn refiltering_touched_cards_leaves_nothing_to_deduce() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let g = human_table(6, 5);
+        let public = Engine::init(&g);
+        while !g.is_over() {
+            let event = play_at_random(&g, &mut rng);
+            public.update_constraints(&event).unwrap();
+            assert!(fully_filtered(&public));
+            for seat in 0..g.num_players() {
+                assert!(fully_filtered(&g.perspective(seat)));
+            }
+        }
+    }
+
+    #[test]
+    fn nothing_is_left_to_filter_after_an_update() {
+        let e = public_engine();
+        e.update_constraints(&ask(0, 1, "5D", AskOutcome::Failure))
+            .unwrap();
+        assert!(e.dirty.borrow().is_empty());
+        assert!(!e.filter().unwrap());
+    }
+
+    #[test]
+    fn rejects_card_given_by_a_player_who_denied_it() {
+        let e = public_engine();
+        e.update_constraints(&ask(0, 1, "5D", AskOutcome::Failure))
+            .unwrap();
+        let err = e
+            .update_constraints(&ask(2, 1, "5D", AskOutcome::Success))
+            .unwrap_err();
+        assert!(matches!(err.kind, Contradiction::ExcludedCard(1, _)));
+        e.check_consistency().unwrap();
+        assert_eq!(e.num_events(), 1);
+    }
+
+    #[test]
+    fn rejects_card_given_by_a_player_who_asked_for_it() {
+        let e = public_engine();
+        e.update_constraints(&ask(0, 3, "4D", AskOutcome::Failure))
+            .unwrap();
+        assert!(e
+            .update_constraints(&ask(3, 0, "4D", AskOutcome::Success))
+            .is_err());
+    }
+
+    #[test]
+    fn rejects_declared_card_from_a_player_who_denied_it() {
+        let e = public_engine();
+        e.update_constraints(&ask(0, 1, "5D", AskOutcome::Failure))
+            .unwrap();
+        let book = e.deck().book("LD").unwrap();
+        let declare = Event::Declare(Declare {
+            declarer: 1,
+            book,
+            claimed_cards: HashMap::from([(1, book.mask())]),
+            actual_cards: HashMap::from([(1, book.mask())]),
+            outcome: crate::DeclareOutcome::Success,
+        });
+        assert!(e.update_constraints(&declare).is_err());
+    }
+
+    #[test]
+    fn rejects_seats_not_at_the_table() {
+        let e = public_engine();
+        let err = e
+            .update_constraints(&ask(0, 9, "4D", AskOutcome::Success))
+            .unwrap_err();
+        assert!(matches!(err.kind, Contradiction::NoSuchPlayer(9)));
+        let err = e
+            .update_constraints(&Event::PassTurn(PassTurn {
+                passer: 7,
+                receiver: 0,
+            }))
+            .unwrap_err();
+        assert!(matches!(err.kind, Contradiction::NoSuchPlayer(7)));
+    }
+}
@@ -0,0 +1,1113 @@
+use crate::card::{Card, CardSet};
+use crate::deck::{Book, Deck};
+use crate::engine::Engine;
+use crate::record::Record;
+use crate::rules::{AfterDeclaration, FailedDeclaration, Rules};
+use crate::save::Save;
+use crate::strategy::{Action, RandomBot, Strategy, View};
+use rand::rngs::StdRng;
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use std::cell::{Ref, RefCell};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::rc::Rc;
+use std::vec::Vec;
+
+/// A game of Fish between two teams seated alternately
+#[derive(Debug)]
+pub struct Fish {
+    teams: Rc<RefCell<Vec<Team>>>,
+    players: Rc<RefCell<Vec<Player>>>,
+    curr_player: Rc<RefCell<usize>>,
+
+    rules: Rc<RefCell<Rules>>,
+    num_players: Rc<RefCell<usize>>,
+    num_humans: Rc<RefCell<u8>>,
+    // Books nobody won because a failed declaration discarded them
+    discarded: Rc<RefCell<Vec<Book>>>,
+
+    // What each seat can deduce from public events and its own hand
+    perspectives: Rc<RefCell<Vec<Engine>>>,
+
+    // Every random choice in the game is drawn from this generator
+    seed: Rc<RefCell<u64>>,
+    rng: Rc<RefCell<StdRng>>,
+
+    // The opening position and everything played since, for the game record
+    deal: Rc<RefCell<Vec<CardSet>>>,
+    first_player: Rc<RefCell<usize>>,
+    history: Rc<RefCell<Vec<Event>>>,
+
+    // The position after each move, the opening first, to take moves back
+    positions: Rc<RefCell<Vec<Position>>>,
+    // Moves taken back, the latest last, until another move is made
+    undone: Rc<RefCell<Vec<Event>>>,
+}
+
+// Where the cards and books are and whose turn it is
+#[derive(Clone, Debug)]
+struct Position {
+    hands: Vec<CardSet>,
+    books: Vec<Vec<Book>>,
+    discarded: Vec<Book>,
+    curr_player: usize,
+}
+
+#[derive(Debug)]
+pub struct Team {
+    pub books: Vec<Book>,
+}
+
+#[derive(Debug)]
+pub struct Player {
+    pub idx: usize,
+    pub cards: CardSet,
+    /// How a bot plays the seat, None for a human
+    pub strategy: Option<Box<dyn Strategy>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Ask {
+    pub asker: usize,
+    pub askee: usize,
+    pub card: Card,
+    pub outcome: AskOutcome,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AskOutcome {
+    Success,
+    Failure,
+}
+
+#[derive(Debug)]
+pub enum AskError {
+    GameOver,
+    BotTurn,
+    SameTeam,
+    PlayerNotFound,
+    InvalidBook,
+    AlreadyOwnCard,
+    /// The askee has no cards left to give
+    NoCards,
+}
+
+#[derive(Debug)]
+pub enum DeclareError {
+    GameOver,
+    BotTurn,
+    PlayerNotFound,
+    AlreadyDeclared,
+    /// A claimed holder is not on the declarer's team
+    NotTeammate,
+    /// A claimed card is not in the book
+    WrongBook,
+    /// The claim does not place every card of the book exactly once
+    Incomplete,
+}
+
+#[derive(Debug)]
+pub enum PassError {
+    GameOver,
+    BotTurn,
+    PlayerNotFound,
+    /// Only a player who cannot ask anyone may pass the turn
+    HasCards,
+    /// The receiver has no cards either
+    NoCards,
+    /// The receiver is an opponent while a teammate still has cards
+    NotTeammate,
+}
+
+#[derive(Debug)]
+pub enum NextError {
+    GameOver,
+    HumanTurn,
+    /// The bot chose an action the rules do not allow
+    IllegalAction(Action),
+}
+
+/// Everything that happens at the table in view of every player
+#[derive(Clone, Debug)]
+pub enum Event {
+    Ask(Ask),
+    Declare(Declare),
+    PassTurn(PassTurn),
+}
+
+/// A player out of cards handing the turn on. Whether the passer has any
+/// cards is public, so everyone learns their hand is empty
+#[derive(Clone, Debug)]
+pub struct PassTurn {
+    pub passer: usize,
+    pub receiver: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct Declare {
+    pub declarer: usize,
+    pub book: Book,
+    /// Where the declarer said each card of the book was
+    pub claimed_cards: HashMap<usize, CardSet>,
+    pub actual_cards: HashMap<usize, CardSet>,
+    pub outcome: DeclareOutcome,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DeclareOutcome {
+    Success,
+    Failure,
+}
+
+/// Book tally and play statistics of a game so far
+#[derive(Clone, Debug)]
+pub struct Summary {
+    /// Books won by each team, in the order they were declared
+    pub books: Vec<Vec<Book>>,
+    /// Books lost to failed declarations that nobody won
+    pub discarded: Vec<Book>,
+    /// The team with more books, once every book is declared
+    pub winner: Option<usize>,
+    pub num_moves: usize,
+    pub stats: Vec<TeamStats>,
+}
+
+/// What one team did over a game
+#[derive(Clone, Debug, Default)]
+pub struct TeamStats {
+    pub asks: usize,
+    pub successful_asks: usize,
+    pub declarations: usize,
+    pub successful_declarations: usize,
+}
+
+impl Fish {
+    /// Deal a new game. The same rules and seed always produce the same
+    /// deal, starting player and bot moves
+    pub fn init(rules: &Rules, num_humans: u8, seed: u64) -> Self {
+        let num_teams = 2;
+        let num_players = rules.num_players;
+
+        // Instantiate deck and shuffle
+        let mut deck = rules.deck().cards().to_vec();
+        let mut rng = StdRng::seed_from_u64(seed);
+        deck.shuffle(&mut rng);
+
+        // Instantiate teams
+        let mut teams = vec![];
+        for _ in 0..num_teams {
+            teams.push(Team { books: vec![] })
+        }
+
+        // Instantiate players (humans and bots)
+        let mut bot_idxs: Vec<usize> = (0..num_players).collect();
+        bot_idxs.shuffle(&mut rng);
+        for _ in 0..num_humans {
+            bot_idxs.pop();
+        }
+
+        let players = rules
+            .hand_sizes()
+            .into_iter()
+            .enumerate()
+            .map(|(idx, hand_size)| Player {
+                idx,
+                cards: deck.drain(0..hand_size).collect(),
+                strategy: if bot_idxs.contains(&idx) {
+                    Some(Box::new(RandomBot::default()) as Box<dyn Strategy>)
+                } else {
+                    None
+                },
+            })
+            .collect();
+        let first_player = rng.random_range(0..num_players);
+        Fish::build(rules, teams, players, first_player, seed, rng)
+    }
+
+    /// Set up a game from a known deal, e.g. one read from a game record.
+    /// Bots draw their moves from `seed`
+    pub fn from_deal(
+        rules: &Rules,
+        deal: &[CardSet],
+        strategies: Vec<Option<Box<dyn Strategy>>>,
+        first_player: usize,
+        seed: u64,
+    ) -> Self {
+        let teams = (0..2).map(|_| Team { books: vec![] }).collect();
+        let players = deal
+            .iter()
+            .zip(strategies)
+            .enumerate()
+            .map(|(idx, (cards, strategy))| Player {
+                idx,
+                cards: *cards,
+                strategy,
+            })
+            .collect();
+        let rng = StdRng::seed_from_u64(seed);
+        Fish::build(rules, teams, players, first_player, seed, rng)
+    }
+
+    fn build(
+        rules: &Rules,
+        teams: Vec<Team>,
+        players: Vec<Player>,
+        first_player: usize,
+        seed: u64,
+        rng: StdRng,
+    ) -> Self {
+        let num_players = players.len();
+        let num_humans = players.iter().filter(|p| p.strategy.is_none()).count() as u8;
+        let deal = players.iter().map(|p| p.cards).collect();
+
+        let game = Fish {
+            teams: Rc::new(RefCell::new(teams)),
+            players: Rc::new(RefCell::new(players)),
+            curr_player: Rc::new(RefCell::new(first_player)),
+
+            rules: Rc::new(RefCell::new(rules.clone())),
+            num_humans: Rc::new(RefCell::new(num_humans)),
+            num_players: Rc::new(RefCell::new(num_players)),
+            discarded: Rc::new(RefCell::new(vec![])),
+
+            perspectives: Rc::new(RefCell::new(vec![])),
+
+            seed: Rc::new(RefCell::new(seed)),
+            rng: Rc::new(RefCell::new(rng)),
+
+            deal: Rc::new(RefCell::new(deal)),
+            first_player: Rc::new(RefCell::new(first_player)),
+            history: Rc::new(RefCell::new(vec![])),
+
+            positions: Rc::new(RefCell::new(vec![])),
+            undone: Rc::new(RefCell::new(vec![])),
+        };
+        game.positions.borrow_mut().push(game.position());
+
+        let perspectives = (0..num_players)
+            .map(|seat| Engine::for_player(&game, seat, game.get_hand(seat)))
+            .collect();
+        *game.perspectives.borrow_mut() = perspectives;
+        game
+    }
+
+    /// Deal a new game, seeded from this one so a session replays exactly.
+    /// Every seat keeps its strategy
+    pub fn reset(&self) {
+        let seed = self.rng.borrow_mut().random();
+        let new_game = Fish::init(&self.rules(), *self.num_humans.borrow(), seed);
+        for (old, new) in self
+            .players
+            .borrow_mut()
+            .iter_mut()
+            .zip(new_game.players.borrow_mut().iter_mut())
+        {
+            new.strategy = old.strategy.take();
+        }
+        self.replace(new_game);
+    }
+
+    /// Take over the state of another game
+    pub fn replace(&self, new_game: Fish) {
+        *self.teams.borrow_mut() = new_game.teams.take();
+        *self.players.borrow_mut() = new_game.players.take();
+        *self.curr_player.borrow_mut() = new_game.curr_player.take();
+        *self.num_players.borrow_mut() = new_game.num_players.take();
+        *self.perspectives.borrow_mut() = new_game.perspectives.take();
+        *self.seed.borrow_mut() = new_game.seed.take();
+        *self.rng.borrow_mut() = new_game.rng.borrow().clone();
+        *self.num_humans.borrow_mut() = new_game.num_humans.take();
+        *self.rules.borrow_mut() = new_game.rules.take();
+        *self.discarded.borrow_mut() = new_game.discarded.take();
+        *self.deal.borrow_mut() = new_game.deal.take();
+        *self.first_player.borrow_mut() = new_game.first_player.take();
+        *self.history.borrow_mut() = new_game.history.take();
+        *self.positions.borrow_mut() = new_game.positions.take();
+        *self.undone.borrow_mut() = new_game.undone.take();
+    }
+
+    pub fn handle_ask(&self, askee_idx: usize, card: &Card) -> Result<Ask, AskError> {
+        let asker_idx = *self.curr_player.borrow();
+        if self.is_over() {
+            return Err(AskError::GameOver);
+        }
+        if self.is_bot(asker_idx) {
+            return Err(AskError::BotTurn);
+        }
+        self.ask(askee_idx, card)
+    }
+
+    pub(crate) fn ask(&self, askee_idx: usize, card: &Card) -> Result<Ask, AskError> {
+        // 1. The player must ask a player from the opposing team
+        // 2. The player must hold a card that is part of the requested book
+        // 3. The player may not ask for a card they already hold
+        // 4. The askee must have cards left
+
+        let asker_idx = *self.curr_player.borrow();
+        if askee_idx >= *self.num_players.borrow() {
+            return Err(AskError::PlayerNotFound);
+        }
+        if askee_idx % 2 == asker_idx % 2 {
+            return Err(AskError::SameTeam);
+        }
+        let book = self.deck().book_of(*card).ok_or(AskError::InvalidBook)?;
+
+        // Get the asker and askee
+        let outcome = {
+            let mut players = self.players.borrow_mut();
+            let (a, b) = players.split_at_mut(std::cmp::max(asker_idx, askee_idx));
+            let (asker, askee) = if askee_idx < asker_idx {
+                (&mut b[0], &mut a[askee_idx])
+            } else {
+                (&mut a[asker_idx], &mut b[0])
+            };
+
+            if !asker.cards.intersects(book.mask()) {
+                return Err(AskError::InvalidBook);
+            }
+            if asker.cards.contains(*card) {
+                return Err(AskError::AlreadyOwnCard);
+            }
+            if askee.cards.is_empty() {
+                return Err(AskError::NoCards);
+            }
+
+            // Check if askee has the requested card
+            // If so, move it to the asker's card list
+            if askee.cards.remove(*card) {
+                asker.cards.insert(*card);
+                AskOutcome::Success
+            } else {
+                *self.curr_player.borrow_mut() = askee_idx;
+                AskOutcome::Failure
+            }
+        };
+
+        let ask = Ask {
+            asker: asker_idx,
+            askee: askee_idx,
+            card: *card,
+            outcome,
+        };
+        self.observe(&Event::Ask(ask.clone()));
+        Ok(ask)
+    }
+
+    /// Let the bot whose turn it is play
+    pub fn handle_next(&self) -> Result<Event, NextError> {
+        if self.is_over() {
+            return Err(NextError::GameOver);
+        }
+        let seat = self.curr_player();
+        let action = {
+            let mut players = self.players.borrow_mut();
+            let hand = players[seat].cards;
+            let hand_sizes = players.iter().map(|p| p.cards.len()).collect();
+            let Some(strategy) = players[seat].strategy.as_mut() else {
+                return Err(NextError::HumanTurn);
+            };
+            let history = self.history.borrow();
+            let view = View {
+                seat,
+                hand,
+                num_players: self.num_players(),
+                hand_sizes,
+                history: &history,
+                engine: &self.perspective(seat),
+            };
+            let mut rng = self.rng.borrow_mut();
+            let action = if hand.is_empty() {
+                None
+            } else {
+                strategy.choose(&view, &mut rng)
+            };
+            action.unwrap_or_else(|| Action::PassTurn {
+                to: strategy.pass_to(&view, &mut rng),
+            })
+        };
+
+        let event = match action {
+            Action::Ask { askee, ref card } => self.ask(askee, card).ok().map(Event::Ask),
+            Action::Declare {
+                book,
+                ref claimed_cards,
+            } => self
+                .handle_declaration(seat, book, claimed_cards.clone())
+                .ok()
+                .map(Event::Declare),
+            Action::PassTurn { to } => self.pass_turn(to).ok().map(Event::PassTurn),
+        };
+        event.ok_or(NextError::IllegalAction(action))
+    }
+
+    /// Hand the turn on from a human who cannot ask anyone, to the given
+    /// player or with None to the next teammate who has cards
+    pub fn handle_pass(&self, to: Option<usize>) -> Result<PassTurn, PassError> {
+        if self.is_bot(self.curr_player()) {
+            return Err(PassError::BotTurn);
+        }
+        self.pass_turn(to)
+    }
+
+    pub(crate) fn pass_turn(&self, to: Option<usize>) -> Result<PassTurn, PassError> {
+        // 1. Only a player who cannot ask anyone passes
+        // 2. The receiver must be someone else with cards
+        // 3. The turn stays with the team while any teammate has cards
+
+        if self.is_over() {
+            return Err(PassError::GameOver);
+        }
+        let passer = self.curr_player();
+        if self.can_ask(passer) {
+            return Err(PassError::HasCards);
+        }
+        let receiver = {
+            let players = self.players.borrow();
+            let n = players.len();
+            let has_cards = |p: &usize| !players[*p].cards.is_empty();
+            let teammate = (1..n / 2).map(|k| (passer + 2 * k) % n).find(has_cards);
+            let receiver = match to
+                .or_else(|| teammate.or_else(|| (1..n).map(|k| (passer + k) % n).find(has_cards)))
+            {
+                Some(receiver) => receiver,
+                // Everything left is the passer's, who has to declare
+                None => return Err(PassError::HasCards),
+            };
+            if receiver >= n {
+                return Err(PassError::PlayerNotFound);
+            }
+            if receiver == passer || !has_cards(&receiver) {
+                return Err(PassError::NoCards);
+            }
+            if receiver % 2 != passer % 2 && teammate.is_some() {
+                return Err(PassError::NotTeammate);
+            }
+            receiver
+        };
+
+        *self.curr_player.borrow_mut() = receiver;
+        let pass = PassTurn { passer, receiver };
+        self.observe(&Event::PassTurn(pass.clone()));
+        Ok(pass)
+    }
+
+    /// Whether the seat may ask anyone for a card: it holds part of a book
+    /// whose other cards it lacks, and an opponent still has cards
+    pub fn can_ask(&self, seat: usize) -> bool {
+        let players = self.players.borrow();
+        let hand = players[seat].cards;
+        let open_book = self
+            .deck()
+            .books()
+            .into_iter()
+            .any(|book| hand.intersects(book.mask()) && !book.mask().is_subset(hand));
+        open_book
+            && players
+                .iter()
+                .any(|p| p.idx % 2 != seat % 2 && !p.cards.is_empty())
+    }
+
+    /// Hand a seat over to a bot, or to a human with None
+    pub fn set_strategy(&self, seat: usize, strategy: Option<Box<dyn Strategy>>) {
+        self.players.borrow_mut()[seat].strategy = strategy;
+        *self.num_humans.borrow_mut() = self
+            .players
+            .borrow()
+            .iter()
+            .filter(|p| p.strategy.is_none())
+            .count() as u8;
+    }
+
+    /// Declare a book for the human whose turn it is
+    pub fn handle_declare(
+        &self,
+        book: Book,
+        claimed_cards: HashMap<usize, CardSet>,
+    ) -> Result<Declare, DeclareError> {
+        let declarer_idx = self.curr_player();
+        if self.is_over() {
+            return Err(DeclareError::GameOver);
+        }
+        if self.is_bot(declarer_idx) {
+            return Err(DeclareError::BotTurn);
+        }
+        self.handle_declaration(declarer_idx, book, claimed_cards)
+    }
+
+    /// Declare a book by saying which teammate holds each of its cards.
+    /// Teammates missing from the claim are said to hold none
+    pub fn handle_declaration(
+        &self,
+        declarer_idx: usize,
+        book: Book,
+        claimed_cards: HashMap<usize, CardSet>,
+    ) -> Result<Declare, DeclareError> {
+        // 1. The book must still be in play
+        // 2. Only the declarer's teammates may be named
+        // 3. Every card of the book must be placed exactly once
+
+        let num_players = self.num_players();
+        if declarer_idx >= num_players {
+            return Err(DeclareError::PlayerNotFound);
+        }
+        if self
+            .teams
+            .borrow()
+            .iter()
+            .any(|team| team.books.contains(&book))
+            || self.discarded.borrow().contains(&book)
+        {
+            return Err(DeclareError::AlreadyDeclared);
+        }
+        let mut placed = CardSet::new();
+        let mut num_placed = 0;
+        for (player, cards) in claimed_cards.iter() {
+            if *player >= num_players {
+                return Err(DeclareError::PlayerNotFound);
+            }
+            if player % 2 != declarer_idx % 2 {
+                return Err(DeclareError::NotTeammate);
+            }
+            if !cards.is_subset(book.mask()) {
+                return Err(DeclareError::WrongBook);
+            }
+            placed |= *cards;
+            num_placed += cards.len();
+        }
+        if placed != book.mask() || num_placed != placed.len() {
+            return Err(DeclareError::Incomplete);
+        }
+
+        let mut players = self.players.borrow_mut();
+        let mut good_declaration: bool = true;
+        let mut actual_cards = HashMap::new();
+
+        for (i, player) in players.iter_mut().enumerate() {
+            // Remove all cards of that book from the player
+            let removed_cards = player.cards & book.mask();
+            player.cards -= removed_cards;
+
+            // Check teammates
+            if i % 2 == declarer_idx % 2 {
+                let guessed_cards = claimed_cards.get(&i).copied().unwrap_or_default();
+                if removed_cards != guessed_cards {
+                    good_declaration = false;
+                }
+            }
+
+            actual_cards.insert(i, removed_cards);
+        }
+
+        drop(players);
+
+        let rules = self.rules();
+        let outcome = {
+            let mut teams = self.teams.borrow_mut();
+            if good_declaration {
+                teams[declarer_idx % 2].books.push(book);
+                DeclareOutcome::Success
+            } else {
+                match rules.failed_declaration {
+                    FailedDeclaration::Opponents => teams[(declarer_idx + 1) % 2].books.push(book),
+                    FailedDeclaration::Discarded => self.discarded.borrow_mut().push(book),
+                }
+                DeclareOutcome::Failure
+            }
+        };
+        match (rules.after_declaration, outcome) {
+            (AfterDeclaration::Unchanged, _) => {}
+            (AfterDeclaration::Declarer, _)
+            | (AfterDeclaration::Outcome, DeclareOutcome::Success) => {
+                *self.curr_player.borrow_mut() = declarer_idx;
+            }
+            (AfterDeclaration::Outcome, DeclareOutcome::Failure) => {
+                *self.curr_player.borrow_mut() = (declarer_idx + 1) % num_players;
+            }
+        }
+
+        let declare = Declare {
+            declarer: declarer_idx,
+            book,
+            claimed_cards,
+            actual_cards,
+            outcome,
+        };
+        self.observe(&Event::Declare(declare.clone()));
+        Ok(declare)
+    }
+
+    /// Let every seat's engine learn from a public event
+    fn observe(&self, event: &Event) {
+        self.history.borrow_mut().push(event.clone());
+        self.positions.borrow_mut().push(self.position());
+        self.undone.borrow_mut().clear();
+        for engine in self.perspectives.borrow().iter() {
+            engine
+                .update_constraints(event)
+                .expect("Public events always agree with a seat's own hand");
+        }
+    }
+
+    fn position(&self) -> Position {
+        Position {
+            hands: self.players.borrow().iter().map(|p| p.cards).collect(),
+            books: self
+                .teams
+                .borrow()
+                .iter()
+                .map(|t| t.books.clone())
+                .collect(),
+            discarded: self.discarded.borrow().clone(),
+            curr_player: self.curr_player(),
+        }
+    }
+
+    /// Take back the last move, in the game and in every seat's engine. It
+    /// can be played again with `redo` until another move is made
+    pub fn undo(&self) -> Option<Event> {
+        let event = self.history.borrow_mut().pop()?;
+        let position = {
+            let mut positions = self.positions.borrow_mut();
+            positions.pop();
+            positions
+                .last()
+                .cloned()
+                .expect("The opening position is never taken back")
+        };
+        for (player, cards) in self.players.borrow_mut().iter_mut().zip(position.hands) {
+            player.cards = cards;
+        }
+        for (team, books) in self.teams.borrow_mut().iter_mut().zip(position.books) {
+            team.books = books;
+        }
+        *self.discarded.borrow_mut() = position.discarded;
+        *self.curr_player.borrow_mut() = position.curr_player;
+        for engine in self.perspectives.borrow().iter() {
+            engine.undo();
+        }
+        self.undone.borrow_mut().push(event.clone());
+        Some(event)
+    }
+
+    /// Play the last move taken back again
+    pub fn redo(&self) -> Option<Event> {
+        let mut undone = self.undone.take();
+        let event = undone.pop()?;
+        let played = self.replay(&event);
+        *self.undone.borrow_mut() = undone;
+        played
+    }
+
+    /// Make a recorded move again, None if it is not legal here. The
+    /// outcome is the game's, which may differ from the recorded one
+    pub(crate) fn replay(&self, event: &Event) -> Option<Event> {
+        Some(match *event {
+            Event::Ask(Ask {
+                asker,
+                askee,
+                ref card,
+                ..
+            }) => {
+                if self.curr_player() != asker {
+                    return None;
+                }
+                Event::Ask(self.ask(askee, card).ok()?)
+            }
+            Event::Declare(Declare {
+                declarer,
+                book,
+                ref claimed_cards,
+                ..
+            }) => Event::Declare(
+                self.handle_declaration(declarer, book, claimed_cards.clone())
+                    .ok()?,
+            ),
+            Event::PassTurn(PassTurn { passer, receiver }) => {
+                if self.curr_player() != passer {
+                    return None;
+                }
+                Event::PassTurn(self.pass_turn(Some(receiver)).ok()?)
+            }
+        })
+    }
+
+    /// Whether every book has been declared
+    pub fn is_over(&self) -> bool {
+        let num_declared: usize = self.teams.borrow().iter().map(|t| t.books.len()).sum();
+        num_declared + self.discarded.borrow().len() == self.rules().books().len()
+    }
+
+    /// The books each team has won and how it played for them
+    pub fn summary(&self) -> Summary {
+        let books: Vec<Vec<Book>> = self
+            .teams
+            .borrow()
+            .iter()
+            .map(|t| t.books.clone())
+            .collect();
+        let winner = if self.is_over() {
+            match books[0].len().cmp(&books[1].len()) {
+                std::cmp::Ordering::Greater => Some(0),
+                std::cmp::Ordering::Less => Some(1),
+                std::cmp::Ordering::Equal => None,
+            }
+        } else {
+            None
+        };
+
+        let history = self.history.borrow();
+        let mut stats = vec![TeamStats::default(); books.len()];
+        for event in history.iter() {
+            match event {
+                Event::Ask(ask) => {
+                    let team = &mut stats[ask.asker % 2];
+                    team.asks += 1;
+                    if ask.outcome == AskOutcome::Success {
+                        team.successful_asks += 1;
+                    }
+                }
+                Event::Declare(declare) => {
+                    let team = &mut stats[declare.declarer % 2];
+                    team.declarations += 1;
+                    if declare.outcome == DeclareOutcome::Success {
+                        team.successful_declarations += 1;
+                    }
+                }
+                Event::PassTurn(_) => {}
+            }
+        }
+
+        Summary {
+            books,
+            discarded: self.discarded.borrow().clone(),
+            winner,
+            num_moves: history
+                .iter()
+                .filter(|event| !matches!(event, Event::PassTurn(_)))
+                .count(),
+            stats,
+        }
+    }
+
+    // Helpers
+    pub fn perspective(&self, seat: usize) -> Ref<'_, Engine> {
+        Ref::map(self.perspectives.borrow(), |engines| &engines[seat])
+    }
+
+    /// Seed of the current deal
+    pub fn seed(&self) -> u64 {
+        *self.seed.borrow()
+    }
+
+    /// Everything needed to replay the game so far
+    pub fn record(&self) -> Record {
+        Record {
+            rules: self.rules(),
+            seed: self.seed(),
+            deal: self.deal.borrow().clone(),
+            strategies: self
+                .players
+                .borrow()
+                .iter()
+                .map(|p| p.strategy.as_ref().map(|s| s.name().to_string()))
+                .collect(),
+            first_player: *self.first_player.borrow(),
+            events: self.history.borrow().clone(),
+        }
+    }
+
+    /// Everything needed to continue the game later. The bots' random
+    /// numbers are reseeded, so a loaded copy plays on exactly like this game
+    pub fn save(&self) -> Save {
+        let rng_seed = self.rng.borrow_mut().random();
+        self.reseed(rng_seed);
+        Save {
+            rng_seed,
+            record: self.record(),
+        }
+    }
+
+    // Continue the bots' random numbers from a new seed
+    pub(crate) fn reseed(&self, seed: u64) {
+        *self.rng.borrow_mut() = StdRng::seed_from_u64(seed);
+    }
+
+    pub fn players(&self) -> Ref<'_, Vec<Player>> {
+        self.players.borrow()
+    }
+
+    pub fn teams(&self) -> Ref<'_, Vec<Team>> {
+        self.teams.borrow()
+    }
+
+    /// Every move so far, oldest first
+    pub fn history(&self) -> Ref<'_, Vec<Event>> {
+        self.history.borrow()
+    }
+
+    pub fn num_events(&self) -> usize {
+        self.history.borrow().len()
+    }
+
+    pub fn get_hand(&self, idx: usize) -> CardSet {
+        self.players.borrow()[idx].cards
+    }
+
+    pub fn curr_player(&self) -> usize {
+        *self.curr_player.borrow()
+    }
+
+    pub fn num_humans(&self) -> usize {
+        *self.num_humans.borrow() as usize
+    }
+
+    pub fn num_bots(&self) -> usize {
+        self.num_players() - self.num_humans()
+    }
+
+    pub fn is_bot(&self, idx: usize) -> bool {
+        self.players.borrow()[idx].strategy.is_some()
+    }
+
+    pub fn num_players(&self) -> usize {
+        *self.num_players.borrow()
+    }
+
+    pub fn num_cards(&self) -> usize {
+        self.rules.borrow().num_cards()
+    }
+
+    pub fn rules(&self) -> Rules {
+        self.rules.borrow().clone()
+    }
+
+    pub fn deck(&self) -> &'static Deck {
+        self.rules.borrow().deck()
+    }
+}
+
+/// Parse one `seat:cards` part of a declaration, e.g. `0:2H,3H`
+pub fn parse_claim(token: &str) -> Option<(usize, CardSet)> {
+    let (player, cards) = token.split_once(':')?;
+    Some((player.parse().ok()?, cards.parse().ok()?))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::engine::tests::knowledge;
+    use rand::seq::IndexedRandom;
+
+    /// A game with humans in every seat, so tests choose every move
+    pub(crate) fn human_table(num_players: usize, seed: u64) -> Fish {
+        let rules = Rules {
+            num_players,
+            ..Rules::default()
+        };
+        Fish::init(&rules, num_players as u8, seed)
+    }
+
+    /// Play a legal move for the human whose turn it is: declare a book the
+    /// team holds, or else ask for a random card
+    pub(crate) fn play_at_random(g: &Fish, rng: &mut StdRng) -> Event {
+        let seat = g.curr_player();
+        let hand = g.get_hand(seat);
+        if hand.is_empty() {
+            return Event::PassTurn(g.handle_pass(None).unwrap());
+        }
+        let live = g.perspective(seat).live_cards();
+        for book in g.deck().books() {
+            let claim: HashMap<usize, CardSet> = (0..g.num_players())
+                .map(|p| (p, g.get_hand(p) & book.mask()))
+                .filter(|(_, cards)| !cards.is_empty())
+                .collect();
+            if book.mask().is_subset(live) && claim.keys().all(|p| p % 2 == seat % 2) {
+                return Event::Declare(g.handle_declare(book, claim).unwrap());
+            }
+        }
+        let books = g
+            .deck()
+            .books()
+            .into_iter()
+            .filter(|book| hand.intersects(book.mask()))
+            .fold(CardSet::new(), |cards, book| cards | book.mask());
+        let asks: Vec<(usize, Card)> = (0..g.num_players())
+            .filter(|p| p % 2 != seat % 2 && !g.get_hand(*p).is_empty())
+            .flat_map(|p| ((books & live) - hand).iter().map(move |card| (p, card)))
+            .collect();
+        let (askee, card) = asks.choose(rng).unwrap();
+        Event::Ask(g.handle_ask(*askee, card).unwrap())
+    }
+
+    #[test]
+    fn undo_and_redo_restore_every_engine() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let g = human_table(6, 4);
+        let seen = |g: &Fish| -> Vec<Vec<String>> {
+            (0..g.num_players())
+                .map(|seat| knowledge(&g.perspective(seat)))
+                .collect()
+        };
+        let mut states = vec![seen(&g)];
+        while !g.is_over() {
+            play_at_random(&g, &mut rng);
+            states.push(seen(&g));
+        }
+        for state in states.iter().rev().skip(1) {
+            g.undo().unwrap();
+            assert_eq!(&seen(&g), state);
+        }
+        for state in states.iter().skip(1) {
+            g.redo().unwrap();
+            assert_eq!(&seen(&g), state);
+        }
+    }
+
+    // A table of four whose seats hold the given numbers of cards
+    fn dealt(sizes: [usize; 4], strategies: Vec<Option<Box<dyn Strategy>>>) -> Fish {
+        let rules = Rules {
+            num_players: 4,
+            ..Rules::default()
+        };
+        let mut cards = rules.deck().cards().iter();
+        let deal: Vec<CardSet> = sizes
+            .iter()
+            .map(|n| cards.by_ref().take(*n).collect())
+            .collect();
+        Fish::from_deal(&rules, &deal, strategies, 0, 0)
+    }
+
+    #[test]
+    fn an_empty_hand_passes_to_a_teammate_first() {
+        let g = dealt([0, 22, 10, 22], (0..4).map(|_| None).collect());
+        assert!(matches!(
+            g.handle_pass(Some(1)),
+            Err(PassError::NotTeammate)
+        ));
+        assert!(matches!(g.handle_pass(Some(0)), Err(PassError::NoCards)));
+        assert!(matches!(
+            g.handle_pass(Some(4)),
+            Err(PassError::PlayerNotFound)
+        ));
+        let pass = g.handle_pass(None).unwrap();
+        assert_eq!((pass.passer, pass.receiver), (0, 2));
+        assert_eq!(g.curr_player(), 2);
+        for seat in 0..4 {
+            let engine = g.perspective(seat);
+            assert!(matches!(engine.event(1), Some(Event::PassTurn(_))));
+            assert!(engine.check_consistency().is_ok());
+        }
+    }
+
+    #[test]
+    fn an_empty_team_passes_to_an_opponent() {
+        let g = dealt([0, 27, 0, 27], (0..4).map(|_| None).collect());
+        let pass = g.handle_pass(None).unwrap();
+        assert_eq!((pass.passer, pass.receiver), (0, 1));
+    }
+
+    #[test]
+    fn a_bot_out_of_cards_passes_instead_of_looping() {
+        let mut strategies: Vec<Option<Box<dyn Strategy>>> = (0..4).map(|_| None).collect();
+        strategies[0] = Some(Box::new(RandomBot::default()));
+        let g = dealt([0, 22, 10, 22], strategies);
+        let Event::PassTurn(pass) = g.handle_next().unwrap() else {
+            panic!("A bot without cards can only pass");
+        };
+        assert_eq!(pass.receiver, 2);
+    }
+
+    // Seat 0 declares the low diamonds out of turn, all in their own hand,
+    // while seat 2 holds one of them. Seat 3 was about to move
+    fn declare_low_diamonds(rules: &Rules, right: bool) -> (Fish, Declare) {
+        let deck = rules.deck();
+        let book = deck.book("LD").unwrap();
+        let mut low = book.mask().to_vec();
+        let last = CardSet::from(low.pop().unwrap());
+        let rest = (deck.cards() - book.mask()).to_vec();
+        let deal = vec![
+            book.mask() - last,
+            rest[..16].iter().copied().collect(),
+            rest[16..32].iter().copied().collect::<CardSet>() | last,
+            rest[32..].iter().copied().collect(),
+        ];
+        let g = Fish::from_deal(rules, &deal, (0..4).map(|_| None).collect(), 3, 0);
+        let claim = if right {
+            HashMap::from([(0, book.mask() - last), (2, last)])
+        } else {
+            HashMap::from([(0, book.mask())])
+        };
+        let declare = g.handle_declaration(0, book, claim).unwrap();
+        (g, declare)
+    }
+
+    #[test]
+    fn a_wrong_declaration_gives_the_book_away_as_the_rules_say() {
+        for failed_declaration in [FailedDeclaration::Opponents, FailedDeclaration::Discarded] {
+            let rules = Rules {
+                num_players: 4,
+                failed_declaration,
+                ..Rules::default()
+            };
+            let (g, declare) = declare_low_diamonds(&rules, false);
+            assert_eq!(declare.outcome, DeclareOutcome::Failure);
+            assert_eq!(declare.actual_cards[&2].len(), 1);
+            let summary = g.summary();
+            assert!(summary.books[0].is_empty());
+            let opponents = summary.books[1].len();
+            let discarded = summary.discarded.len();
+            match failed_declaration {
+                FailedDeclaration::Opponents => assert_eq!((opponents, discarded), (1, 0)),
+                FailedDeclaration::Discarded => assert_eq!((opponents, discarded), (0, 1)),
+            }
+        }
+    }
+
+    #[test]
+    fn the_rules_say_who_moves_after_a_declaration() {
+        use AfterDeclaration::*;
+        for (after_declaration, right, next) in [
+            (Unchanged, true, 3),
+            (Unchanged, false, 3),
+            (Declarer, true, 0),
+            (Declarer, false, 0),
+            (Outcome, true, 0),
+            (Outcome, false, 1),
+        ] {
+            let rules = Rules {
+                num_players: 4,
+                after_declaration,
+                ..Rules::default()
+            };
+            let (g, declare) = declare_low_diamonds(&rules, right);
+            assert_eq!(declare.outcome == DeclareOutcome::Success, right);
+            assert_eq!(g.summary().books[0].len(), usize::from(right));
+            assert_eq!(g.curr_player(), next);
+        }
+    }
+
+    #[test]
+    fn only_a_seat_that_cannot_ask_passes() {
+        let rules = Rules {
+            num_players: 4,
+            ..Rules::default()
+        };
+        let deck = rules.deck();
+        let book = deck.book("LD").unwrap().mask();
+        let rest = (deck.cards() - book).to_vec();
+        let deal = vec![
+            book,
+            rest[..16].iter().copied().collect(),
+            rest[16..32].iter().copied().collect(),
+            rest[32..].iter().copied().collect(),
+        ];
+        let g = Fish::from_deal(&rules, &deal, (0..4).map(|_| None).collect(), 0, 0);
+
+        // Seat 0 holds one whole book and nothing else
+        assert!(!g.can_ask(0));
+        let pass = g.handle_pass(None).unwrap();
+        assert_eq!(pass.receiver, 2);
+        assert!(g.perspective(1).check_consistency().is_ok());
+        assert!(matches!(g.handle_pass(None), Err(PassError::HasCards)));
+    }
+}
@@ -0,0 +1,85 @@
+// Several humans can share one screen by taking turns at it. Only the human
+// whose turn it is sits at the screen: they may see their own hand and the
+// public moves, but not the other hands, and not the game record until the
+// game is over since it lists the deal. A human coming back to the screen
+// is shown the moves made since they last sat there
+use crate::game::{Event, Fish};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Who may see what while humans take turns at one screen. When disabled
+/// everyone sees everything
+#[derive(Debug)]
+pub struct HotSeat {
+    enabled: Rc<RefCell<bool>>,
+    // The human at the screen
+    viewer: Rc<RefCell<Option<usize>>>,
+    // Moves each seat has been shown
+    seen: Rc<RefCell<Vec<usize>>>,
+}
+
+impl HotSeat {
+    /// Nobody is at the screen yet, and the moves of the game so far have
+    /// been seen
+    pub fn new(enabled: bool, g: &Fish) -> Self {
+        HotSeat {
+            enabled: Rc::new(RefCell::new(enabled)),
+            viewer: Rc::new(RefCell::new(None)),
+            seen: Rc::new(RefCell::new(vec![g.num_events(); g.num_players()])),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        *self.enabled.borrow()
+    }
+
+    /// The human at the screen, if anyone sits there yet
+    pub fn viewer(&self) -> Option<usize> {
+        *self.viewer.borrow()
+    }
+
+    /// Whether the screen may show a seat's hand
+    pub fn may_see(&self, seat: usize) -> bool {
+        !self.is_enabled() || self.viewer() == Some(seat)
+    }
+
+    /// Whether the record must stay hidden, as it lists the deal
+    pub fn hides_record(&self, g: &Fish) -> bool {
+        self.is_enabled() && !g.is_over()
+    }
+
+    /// Nobody is at the screen of a new or loaded game, and its moves so
+    /// far have been seen
+    pub fn reset(&self, g: &Fish) {
+        *self.viewer.borrow_mut() = None;
+        *self.seen.borrow_mut() = vec![g.num_events(); g.num_players()];
+    }
+
+    /// The human who must take the screen before play goes on, None if the
+    /// one sitting there may stay
+    pub fn next_viewer(&self, g: &Fish) -> Option<usize> {
+        let seat = g.curr_player();
+        (self.is_enabled() && !g.is_over() && !g.is_bot(seat) && self.viewer() != Some(seat))
+            .then_some(seat)
+    }
+
+    /// Seat a human at the screen
+    pub fn sit(&self, seat: usize) {
+        *self.viewer.borrow_mut() = Some(seat);
+    }
+
+    /// Moves a seat has not been shown yet, which now count as shown
+    pub fn unseen(&self, g: &Fish, seat: usize) -> Vec<Event> {
+        let history = g.history();
+        let from = std::mem::replace(&mut self.seen.borrow_mut()[seat], history.len());
+        history[from..].to_vec()
+    }
+
+    /// Forget having shown moves that were taken back
+    pub fn take_back(&self, g: &Fish) {
+        let num_events = g.num_events();
+        for n in self.seen.borrow_mut().iter_mut() {
+            *n = (*n).min(num_events);
+        }
+    }
+}
@@ -0,0 +1,644 @@
+// Single-observer information set Monte Carlo tree search. Every iteration
+// draws one deal consistent with the bot's engine, walks the tree of asks
+// and declarations that deal allows, plays on, and scores the result. Each
+// node records whether the searching team made its move, so it is judged
+// from that team's side.
+//
+// The bot may declare any book its engine cannot rule out for the team. It
+// claims the placement that the deals drawn so far agree on most often, so
+// a declaration is only credited on the deals that place the book exactly
+// so, which is how a risky declaration is weighed against asking on. Of its
+// asks only the `width` found most often in the deals are searched.
+//
+// Past the bot's own move every player goes by their own hand and what the
+// asks so far have shown of the others, never by the drawn deal. They
+// consider the asks likeliest to find a card on that knowledge and declare
+// the books they know their team holds. Once the opponents are out of
+// cards a team must declare whatever it holds, and such a guess is credited
+// by its chance of placing the unseen cards right. A failed declaration
+// gives the book to the other team, and a deal is scored by the books each
+// team has declared plus its share of the cards of every other book
+use crate::card::{Card, CardSet};
+use crate::deck::{Book, Deck};
+use crate::engine::{Deal, DealSampler};
+use crate::game::{AskOutcome, Event};
+use crate::strategy::{legal_asks, no_legal_ask, Action, Strategy, View};
+use rand::rngs::StdRng;
+use rand::seq::IndexedRandom;
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+// Deals drawn to find the asks likeliest to succeed
+const ASK_SAMPLES: usize = 100;
+
+/// Searches the asks and declarations available to it over deals drawn from
+/// its engine
+#[derive(Debug)]
+pub struct IsmctsBot {
+    /// Deals searched per move, one per iteration
+    pub iterations: usize,
+    /// Stop early once this much time has passed, counting the time spent
+    /// drawing deals. Seeded games only replay exactly without a time limit
+    pub time_limit: Option<Duration>,
+    /// Moves played past the tree before a deal is scored
+    pub rollout_depth: usize,
+    /// Weight of exploration against exploitation when picking a child
+    pub exploration: f32,
+    /// Asks searched at the root, those likeliest to find a card
+    pub width: usize,
+}
+
+impl Default for IsmctsBot {
+    fn default() -> Self {
+        IsmctsBot {
+            iterations: 500,
+            time_limit: None,
+            rollout_depth: 20,
+            exploration: 0.7,
+            width: 2,
+        }
+    }
+}
+
+impl Strategy for IsmctsBot {
+    fn name(&self) -> &'static str {
+        "ismcts"
+    }
+
+    fn choose(&mut self, view: &View, rng: &mut StdRng) -> Option<Action> {
+        match self.search(view, rng) {
+            Some((Move::Ask(askee, card), _)) => Some(Action::Ask { askee, card }),
+            Some((Move::Declare(book), claims)) => Some(Action::Declare {
+                book,
+                claimed_cards: claims[&book].iter().copied().collect(),
+            }),
+            None => no_legal_ask(view, rng),
+        }
+    }
+}
+
+impl IsmctsBot {
+    /// The defaults overridden by comma separated settings, e.g.
+    /// `iterations=200,time=500` with the time in milliseconds. None if a
+    /// setting is unknown or its value cannot be read
+    pub fn with_settings(settings: &str) -> Option<Self> {
+        let mut bot = IsmctsBot::default();
+        for setting in settings.split(',').filter(|s| !s.is_empty()) {
+            match setting.split_once('=')? {
+                ("iterations", n) => bot.iterations = n.parse().ok()?,
+                ("time", ms) => bot.time_limit = Some(Duration::from_millis(ms.parse().ok()?)),
+                ("depth", n) => bot.rollout_depth = n.parse().ok()?,
+                ("exploration", x) => bot.exploration = x.parse().ok()?,
+                ("width", n) => bot.width = n.parse().ok()?,
+                _ => return None,
+            }
+        }
+        Some(bot)
+    }
+
+    /// The most visited move at the root with the placement the bot would
+    /// claim for each book, None if no deal was searched or the bot has no
+    /// move to make
+    fn search(&self, view: &View, rng: &mut StdRng) -> Option<(Move, Claims)> {
+        let start = Instant::now();
+        let team = view.seat % 2;
+        let mut tree = vec![Node::new(None)];
+        let mut sampler = view.engine.deal_sampler(rng);
+
+        // How often the deals drawn so far place each book within the team
+        let declarable: Vec<Book> = view
+            .engine
+            .books_in_play()
+            .into_iter()
+            .filter(|book| view.engine.may_be_within(*book, team))
+            .collect();
+        let mut placements: BTreeMap<Book, BTreeMap<Placement, usize>> = BTreeMap::new();
+        let mut claims = Claims::new();
+        let public = Knowledge::new(view);
+        let asks = likely_asks(view, self.width, &mut sampler, rng);
+
+        for _ in 0..self.iterations {
+            if self
+                .time_limit
+                .is_some_and(|limit| start.elapsed() >= limit)
+            {
+                break;
+            }
+            let deal = sampler.next_deal(rng);
+            let mut state = SimState::new(&deal, view, &public);
+            for book in declarable.iter() {
+                let placement = state.placement(*book);
+                if placement.iter().all(|(p, _)| p % 2 == team) {
+                    let counts = placements.entry(*book).or_default();
+                    *counts.entry(placement).or_default() += 1;
+                    let (likeliest, _) = counts.iter().max_by_key(|(_, n)| **n).unwrap();
+                    claims.insert(*book, likeliest.clone());
+                }
+            }
+            state.claims = Some(claims.clone());
+            let mut path = vec![0];
+            let mut node = 0;
+
+            // Select down the tree until a move this deal allows is untried
+            while !state.is_over() {
+                let mut moves = state.legal_moves();
+                if moves.is_empty() {
+                    // The bot has nothing to search and hands the turn on
+                    if node == 0 {
+                        return None;
+                    }
+                    state.pass();
+                    continue;
+                }
+                if node == 0 {
+                    moves.retain(|mv| matches!(mv, Move::Declare(..)) || asks.contains(mv));
+                } else {
+                    moves = state.likely_moves(self.width);
+                }
+                let available: Vec<usize> = tree[node]
+                    .children
+                    .iter()
+                    .copied()
+                    .filter(|child| moves.contains(tree[*child].mv.as_ref().unwrap()))
+                    .collect();
+                for child in available.iter() {
+                    tree[*child].available += 1.0;
+                }
+                let untried: Vec<&Move> = moves
+                    .iter()
+                    .filter(|mv| {
+                        !tree[node]
+                            .children
+                            .iter()
+                            .any(|child| tree[*child].mv.as_ref() == Some(*mv))
+                    })
+                    .collect();
+
+                if let Some(mv) = untried.choose(rng) {
+                    let child = tree.len();
+                    tree.push(Node::new(Some((*mv).clone())));
+                    tree[child].available = 1.0;
+                    tree[child].ours = state.curr_player % 2 == team;
+                    tree[node].children.push(child);
+                    state.play(mv);
+                    path.push(child);
+                    break;
+                }
+
+                let child = available
+                    .into_iter()
+                    .max_by(|a, b| {
+                        let (a, b) = (&tree[*a], &tree[*b]);
+                        a.ucb(self.exploration).total_cmp(&b.ucb(self.exploration))
+                    })
+                    .unwrap();
+                state.play(tree[child].mv.as_ref().unwrap());
+                path.push(child);
+                node = child;
+            }
+
+            // Play on as each player would on what they know, then credit
+            // every node on the path
+            for _ in 0..self.rollout_depth {
+                if state.is_over() {
+                    break;
+                }
+                match state.informed_move(rng) {
+                    Some(mv) => state.play(&mv),
+                    None => state.pass(),
+                }
+            }
+            let reward = state.score(team);
+            for idx in path {
+                tree[idx].visits += 1.0;
+                tree[idx].reward += reward;
+            }
+        }
+
+        tree[0]
+            .children
+            .iter()
+            .max_by(|a, b| tree[**a].visits.total_cmp(&tree[**b].visits))
+            .and_then(|child| tree[*child].mv.clone())
+            .map(|mv| (mv, claims))
+    }
+}
+
+// The `width` asks found most often in deals drawn from the sampler
+fn likely_asks(
+    view: &View,
+    width: usize,
+    sampler: &mut DealSampler,
+    rng: &mut StdRng,
+) -> Vec<Move> {
+    let deals: Vec<Deal> = (0..ASK_SAMPLES).map(|_| sampler.next_deal(rng)).collect();
+    let mut asks: Vec<(usize, Card, usize)> = legal_asks(view)
+        .into_iter()
+        .map(|(askee, card)| {
+            let found = deals
+                .iter()
+                .filter(|deal| deal[&askee].contains(card))
+                .count();
+            (askee, card, found)
+        })
+        .collect();
+    // Between asks as likely, the books the bot holds more of are nearer
+    // to being declared
+    let held = |card: Card| {
+        let book = view
+            .engine
+            .deck()
+            .book_of(card)
+            .expect("Only cards of the deck are asked for");
+        (view.hand & book.mask()).len()
+    };
+    asks.sort_by_key(|(_, card, found)| std::cmp::Reverse((*found, held(*card))));
+    asks.into_iter()
+        .take(width)
+        .map(|(askee, card, _)| Move::Ask(askee, card))
+        .collect()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Move {
+    Ask(usize, Card),
+    Declare(Book),
+}
+
+// Where the cards of a book are, ordered by seat
+type Placement = Vec<(usize, CardSet)>;
+
+// What the searching bot would claim for each book it may declare
+type Claims = BTreeMap<Book, Placement>;
+
+struct Node {
+    /// The move leading here, None at the root
+    mv: Option<Move>,
+    visits: f32,
+    /// Total score of the searching team over the visits
+    reward: f32,
+    /// Visits to the parent in which this move was allowed
+    available: f32,
+    children: Vec<usize>,
+    /// Whether the move is made by the searching team
+    ours: bool,
+}
+
+impl Node {
+    fn new(mv: Option<Move>) -> Self {
+        Node {
+            mv,
+            visits: 0.0,
+            reward: 0.0,
+            available: 0.0,
+            children: vec![],
+            ours: true,
+        }
+    }
+
+    // Upper confidence bound from the side of the team making the move
+    fn ucb(&self, exploration: f32) -> f32 {
+        let mean = self.reward / self.visits;
+        let value = if self.ours { mean } else { 1.0 - mean };
+        value + exploration * (self.available.ln() / self.visits).sqrt()
+    }
+}
+
+// What every player can tell about the hands from the asks made so far
+#[derive(Clone)]
+struct Knowledge {
+    /// Cards each seat was seen to take and has not been seen to lose
+    held: Vec<CardSet>,
+    /// Cards each seat was seen to lack and has not been seen to take
+    lacks: Vec<CardSet>,
+}
+
+impl Knowledge {
+    fn new(view: &View) -> Self {
+        let mut knowledge = Knowledge {
+            held: vec![CardSet::new(); view.num_players],
+            lacks: vec![CardSet::new(); view.num_players],
+        };
+        for event in view.history {
+            match event {
+                Event::Ask(ask) => knowledge.asked(
+                    ask.asker,
+                    ask.askee,
+                    ask.card,
+                    ask.outcome == AskOutcome::Success,
+                ),
+                Event::Declare(declare) => knowledge.declared(declare.book),
+                Event::PassTurn(_) => {}
+            }
+        }
+        knowledge
+    }
+
+    fn asked(&mut self, asker: usize, askee: usize, card: Card, taken: bool) {
+        if taken {
+            self.held[askee].remove(card);
+            self.lacks[askee].insert(card);
+            self.held[asker].insert(card);
+            self.lacks[asker].remove(card);
+        } else {
+            self.lacks[askee].insert(card);
+            self.lacks[asker].insert(card);
+        }
+    }
+
+    fn declared(&mut self, book: Book) {
+        for cards in self.held.iter_mut().chain(self.lacks.iter_mut()) {
+            *cards -= book.mask();
+        }
+    }
+}
+
+// One determinized deal played forward
+struct SimState {
+    deck: &'static Deck,
+    hands: Vec<CardSet>,
+    curr_player: usize,
+    /// Books each team has declared since the search started
+    books: [f32; 2],
+    /// Cards of books still to be declared
+    live: CardSet,
+    /// What the searching bot claims while it is still its move, None once
+    /// the search has moved past the root
+    claims: Option<Claims>,
+    /// All that players past the root go by besides their own hand
+    public: Knowledge,
+}
+
+impl SimState {
+    fn new(deal: &Deal, view: &View, public: &Knowledge) -> Self {
+        let hands = (0..view.num_players).map(|p| deal[&p]).collect();
+        SimState {
+            deck: view.engine.deck(),
+            hands,
+            curr_player: view.seat,
+            books: [0.0, 0.0],
+            live: view.engine.live_cards(),
+            claims: None,
+            public: public.clone(),
+        }
+    }
+
+    fn is_over(&self) -> bool {
+        self.live.is_empty()
+    }
+
+    fn legal_moves(&self) -> Vec<Move> {
+        let mut moves: Vec<Move> = self
+            .legal_asks()
+            .into_iter()
+            .map(|(askee, card)| Move::Ask(askee, card))
+            .collect();
+        moves.extend(self.declarations());
+        moves
+    }
+
+    fn declarations(&self) -> Vec<Move> {
+        self.deck
+            .books()
+            .into_iter()
+            .filter(|book| self.may_declare(*book))
+            .map(Move::Declare)
+            .collect()
+    }
+
+    // The searching bot may declare whatever it has a claim for. Everyone
+    // after it declares the books they know their team holds, or any book
+    // the team holds once the opponents are out of cards and it must
+    fn may_declare(&self, book: Book) -> bool {
+        if !book.mask().is_subset(self.live) {
+            return false;
+        }
+        let seat = self.curr_player;
+        match &self.claims {
+            Some(claims) => claims.contains_key(&book),
+            None if self.knows_team_holds(book) => true,
+            None => {
+                (0..self.hands.len()).all(|p| p % 2 == seat % 2 || self.hands[p].is_empty())
+                    && self.placement(book).iter().all(|(p, _)| p % 2 == seat % 2)
+            }
+        }
+    }
+
+    // Whether the player to move can place every card of the book within
+    // the team from their hand and the cards teammates were seen to take
+    fn knows_team_holds(&self, book: Book) -> bool {
+        let seat = self.curr_player;
+        let known = (0..self.hands.len())
+            .filter(|p| p % 2 == seat % 2 && *p != seat)
+            .fold(self.hands[seat], |cards, p| cards | self.public.held[p]);
+        book.mask().is_subset(known)
+    }
+
+    // The moves the player to move would consider on what they can tell:
+    // the books they may declare and the `width` asks likeliest to find a
+    // card
+    fn likely_moves(&self, width: usize) -> Vec<Move> {
+        let mut asks = self.legal_asks();
+        asks.sort_by(|a, b| self.chance(b.0, b.1).total_cmp(&self.chance(a.0, a.1)));
+        let mut moves = self.declarations();
+        moves.extend(
+            asks.into_iter()
+                .take(width)
+                .map(|(askee, card)| Move::Ask(askee, card)),
+        );
+        moves
+    }
+
+    // What the player to move does on what they can tell: declare a book
+    // the team is known to hold, or else ask for a card where it is most
+    // likely to be, ties broken at random
+    fn informed_move(&self, rng: &mut StdRng) -> Option<Move> {
+        let moves = self.legal_moves();
+        if let Some(declare) = moves.iter().find(|mv| matches!(mv, Move::Declare(..))) {
+            return Some(declare.clone());
+        }
+        let chances: Vec<f32> = moves
+            .iter()
+            .map(|mv| match mv {
+                Move::Ask(askee, card) => self.chance(*askee, *card),
+                Move::Declare(..) => 0.0,
+            })
+            .collect();
+        let best = chances.iter().copied().fold(0.0, f32::max);
+        let likeliest: Vec<&Move> = moves
+            .iter()
+            .zip(chances)
+            .filter(|(_, p)| *p == best)
+            .map(|(mv, _)| mv)
+            .collect();
+        likeliest.choose(rng).map(|mv| (*mv).clone())
+    }
+
+    // How likely the player to move thinks `askee` is to hold the card:
+    // certain if they were seen to take it, otherwise an even share among
+    // the other players not known to lack it
+    fn chance(&self, askee: usize, card: Card) -> f32 {
+        if self.public.held[askee].contains(card) {
+            return 1.0;
+        }
+        let seat = self.curr_player;
+        let holders: Vec<usize> = (0..self.hands.len())
+            .filter(|p| *p != seat && !self.hands[*p].is_empty())
+            .filter(|p| !self.public.lacks[*p].contains(card))
+            .collect();
+        let seen_elsewhere = holders.iter().any(|p| self.public.held[*p].contains(card));
+        if seen_elsewhere || !holders.contains(&askee) {
+            0.0
+        } else {
+            1.0 / holders.len() as f32
+        }
+    }
+
+    fn placement(&self, book: Book) -> Placement {
+        (0..self.hands.len())
+            .map(|p| (p, self.hands[p] & book.mask()))
+            .filter(|(_, cards)| !cards.is_empty())
+            .collect()
+    }
+
+    fn legal_asks(&self) -> Vec<(usize, Card)> {
+        let hand = self.hands[self.curr_player];
+        let books = self
+            .deck
+            .books()
+            .into_iter()
+            .filter(|book| hand.intersects(book.mask()))
+            .fold(CardSet::new(), |cards, book| cards | book.mask());
+        let askable = (books & self.live) - hand;
+        (0..self.hands.len())
+            .filter(|p| p % 2 != self.curr_player % 2 && !self.hands[*p].is_empty())
+            .flat_map(|p| askable.iter().map(move |card| (p, card)))
+            .collect()
+    }
+
+    fn play(&mut self, mv: &Move) {
+        match mv {
+            Move::Ask(askee, card) => self.ask(*askee, *card),
+            Move::Declare(book) => self.declare(*book),
+        }
+        self.claims = None;
+    }
+
+    fn ask(&mut self, askee: usize, card: Card) {
+        let asker = self.curr_player;
+        let taken = self.hands[askee].remove(card);
+        if taken {
+            self.hands[asker].insert(card);
+        } else {
+            self.curr_player = askee;
+        }
+        self.public.asked(asker, askee, card, taken);
+        if self.hands[self.curr_player].is_empty() {
+            self.pass();
+        }
+    }
+
+    fn declare(&mut self, book: Book) {
+        let team = self.curr_player % 2;
+        let placement = self.placement(book);
+        let correct = match &self.claims {
+            Some(claims) if claims[&book] == placement => 1.0,
+            Some(_) => 0.0,
+            None => self.chance_placed(book),
+        };
+        for hand in self.hands.iter_mut() {
+            *hand -= book.mask();
+        }
+        self.public.declared(book);
+        self.live -= book.mask();
+        self.books[team] += correct;
+        self.books[1 - team] += 1.0 - correct;
+        if !self.is_over() && self.hands[self.curr_player].is_empty() {
+            self.pass();
+        }
+    }
+
+    // How likely the player to move is to place the book right: every card
+    // they cannot place is a guess among the teammates who may hold it
+    fn chance_placed(&self, book: Book) -> f32 {
+        let seat = self.curr_player;
+        if self.placement(book).iter().any(|(p, _)| p % 2 != seat % 2) {
+            return 0.0;
+        }
+        let teammates: Vec<usize> = (0..self.hands.len())
+            .filter(|p| p % 2 == seat % 2 && *p != seat && !self.hands[*p].is_empty())
+            .collect();
+        let placed = teammates
+            .iter()
+            .fold(self.hands[seat], |cards, p| cards | self.public.held[*p]);
+        (book.mask() - placed)
+            .iter()
+            .map(|card| {
+                let holders = teammates
+                    .iter()
+                    .filter(|p| !self.public.lacks[**p].contains(card))
+                    .count();
+                1.0 / holders.max(1) as f32
+            })
+            .product()
+    }
+
+    // Hand the turn to the next player with cards, teammates first
+    fn pass(&mut self) {
+        let n = self.hands.len();
+        let seat = self.curr_player;
+        let next = (1..n / 2)
+            .map(|k| (seat + 2 * k) % n)
+            .chain((1..n).map(|k| (seat + k) % n))
+            .find(|p| !self.hands[*p].is_empty());
+        self.claims = None;
+        if let Some(next) = next {
+            self.curr_player = next;
+        } else {
+            self.live = CardSet::new();
+        }
+    }
+
+    /// Share of the books in play at the start of the search that end up
+    /// with `team`, counting each undeclared book by the cards it holds
+    fn score(&self, team: usize) -> f32 {
+        let mut points = self.books[team];
+        let mut total = self.books[0] + self.books[1];
+        for book in self
+            .deck
+            .books()
+            .into_iter()
+            .filter(|b| b.mask().is_subset(self.live))
+        {
+            let held = (0..self.hands.len())
+                .filter(|p| p % 2 == team)
+                .map(|p| (self.hands[p] & book.mask()).len())
+                .sum::<usize>();
+            points += held as f32 / book.mask().len() as f32;
+            total += 1.0;
+        }
+        if total == 0.0 {
+            0.5
+        } else {
+            points / total
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategy;
+
+    #[test]
+    fn reads_settings_after_the_name() {
+        let bot = IsmctsBot::with_settings("iterations=200,time=500").unwrap();
+        assert_eq!(bot.iterations, 200);
+        assert_eq!(bot.time_limit, Some(Duration::from_millis(500)));
+        assert_eq!(bot.rollout_depth, IsmctsBot::default().rollout_depth);
+        assert!(IsmctsBot::with_settings("iterations=many").is_none());
+        assert!(IsmctsBot::with_settings("speed=5").is_none());
+        assert!(strategy::by_name("ismcts:depth=5,exploration=1.5").is_some());
+        assert!(strategy::by_name("smart:iterations=5").is_none());
+    }
+}
@@ -0,0 +1,29 @@
+//! Cards, game state, events and the inference engine behind the Fish REPL
+
+pub mod card;
+pub mod client;
+pub mod deck;
+pub mod engine;
+pub mod game;
+pub mod hot_seat;
+pub mod ismcts;
+mod matching;
+pub mod printer;
+pub mod record;
+pub mod rules;
+pub mod save;
+pub mod server;
+pub mod simulate;
+pub mod strategy;
+
+pub use crate::card::{Card, CardSet};
+pub use crate::deck::{Book, Deck};
+pub use crate::engine::Engine;
+pub use crate::game::{
+    Ask, AskError, AskOutcome, Declare, DeclareError, DeclareOutcome, Event, Fish, NextError,
+    PassError, PassTurn, Player, Summary, Team, TeamStats,
+};
+pub use crate::record::{Record, Replay};
+pub use crate::rules::Rules;
+pub use crate::save::Save;
+pub use crate::strategy::{Action, Strategy};
@@ -1,369 +1,198 @@
-// TODO: Extend engine to work with any number of cards and books
-
-use clap::Parser;
-use colored::Colorize;
-use easy_repl::{command, CommandStatus, Repl};
-use rand::{rng, seq::SliceRandom, Rng};
+use clap::{Parser, Subcommand};
+use easy_repl::anyhow::anyhow;
+use easy_repl::command::ArgsError;
+use easy_repl::{command, Command, CommandStatus, Repl};
+use fish::client::{self, ServerMessage, Table};
+use fish::game::parse_claim;
+use fish::hot_seat::HotSeat;
+use fish::printer::Printer;
+use fish::rules::{AfterDeclaration, DeckKind, FailedDeclaration};
+use fish::server::Server;
+use fish::simulate::Tournament;
+use fish::strategy;
+use fish::{
+    Ask, AskError, AskOutcome, Card, DeclareError, DeclareOutcome, Engine, Event, Fish, NextError,
+    PassError, Record, Replay, Rules, Save,
+};
+use rand::rng;
 use std::cell::RefCell;
-use std::collections::{HashMap, HashSet};
-use std::fmt::Debug;
-use std::io;
+use std::collections::HashMap;
 use std::io::Write;
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
-use std::vec::Vec;
-
-mod card;
-use crate::card::{Book, Card};
-
-mod engine;
-use crate::engine::Engine;
-
-mod printer;
-use crate::printer::{PrettyDisplay, Printer};
-
-#[derive(Debug)]
-struct Fish {
-    teams: Rc<RefCell<Vec<Team>>>,
-    players: Rc<RefCell<Vec<Player>>>,
-    curr_player: Rc<RefCell<usize>>,
-
-    num_players: Rc<RefCell<usize>>,
-    num_humans: Rc<RefCell<u8>>,
-    num_cards: Rc<RefCell<usize>>,
-}
-
-#[derive(Debug)]
-struct Team {
-    books: Vec<Book>,
-}
+use std::sync::{Arc, Mutex};
+use std::thread;
 
-#[derive(Debug)]
-struct Player {
-    idx: usize,
-    cards: Vec<Card>,
-    is_bot: bool,
-}
-
-#[derive(Debug)]
-struct Ask {
-    asker: usize,
-    askee: usize,
-    card: Card,
-    outcome: AskOutcome,
-}
-
-#[derive(Copy, Clone, Debug)]
-enum AskOutcome {
-    Success,
-    Failure,
-}
-
-#[derive(Debug)]
-enum AskError {
-    BotTurn,
-    SameTeam,
-    PlayerNotFound,
-    InvalidBook,
-    AlreadyOwnCard,
-}
-
-#[derive(Debug)]
-enum NextError {
-    HumanTurn,
-}
-
-#[derive(Debug)]
-enum Event {
-    Ask(Ask),
-    Declare(Declare),
-}
-
-#[derive(Debug)]
-struct Declare {
-    book: Book,
-    actual_cards: HashMap<usize, HashSet<Card>>,
-    outcome: DeclareOutcome,
+#[derive(Parser)]
+struct Args {
+    #[command(subcommand)]
+    mode: Option<Mode>,
+    #[clap(required = false, long, default_value = "0")]
+    num_humans: u8,
+    /// Clear the screen between human turns and show each human only what
+    /// their seat may see
+    #[clap(long)]
+    hot_seat: bool,
+    /// Continue a game saved with the save command
+    #[clap(long)]
+    load: Option<PathBuf>,
+    /// Save the game to this file when leaving
+    #[clap(long)]
+    save: Option<PathBuf>,
+    /// Seed for the deal and the bots, random if omitted
+    #[clap(long, global = true)]
+    seed: Option<u64>,
+    /// How the bots play: random, smart or ismcts. ISMCTS takes settings,
+    /// e.g. ismcts:iterations=200,time=500 with the time in milliseconds
+    #[clap(long, global = true, default_value = "random", value_parser = strategy_name)]
+    bot: String,
+    /// TOML file with the table rules. The flags below override it
+    #[clap(long, global = true)]
+    rules: Option<PathBuf>,
+    /// Number of players: 4, 6 or 8
+    #[clap(long, global = true)]
+    players: Option<usize>,
+    /// How the cards make books: fish (half suits) or go-fish (ranks)
+    #[clap(long, global = true)]
+    deck: Option<DeckKind>,
+    /// Leave out the eights, and the jokers from a fish deck
+    #[clap(long, global = true)]
+    no_eights: bool,
+    /// Where a wrongly declared book goes: opponents or discarded
+    #[clap(long, global = true)]
+    failed_declaration: Option<FailedDeclaration>,
+    /// Who moves after a declaration: unchanged, declarer or outcome
+    #[clap(long, global = true)]
+    after_declaration: Option<AfterDeclaration>,
 }
 
-#[derive(Debug)]
-enum DeclareOutcome {
-    Success,
-    Failure,
+#[derive(Subcommand)]
+enum Mode {
+    /// Step through a game record
+    Replay { path: PathBuf },
+    /// Play bot-only games between strategies and compare their win rates
+    Simulate {
+        /// Deals to play. Each pair of strategies plays every deal from both sides
+        #[clap(long, default_value = "50")]
+        deals: usize,
+        /// Strategies taking part, at least two, named as for --bot
+        #[clap(num_args = 2.., default_values = ["smart", "random"], value_parser = strategy_name)]
+        strategies: Vec<String>,
+    },
+    /// Host a game over TCP, with bots in the seats nobody claims
+    Serve {
+        #[clap(long, default_value = "7878")]
+        port: u16,
+        /// Seats to be claimed before play starts
+        #[clap(long, default_value = "1")]
+        humans: usize,
+    },
+    /// Join a game hosted with serve
+    Connect {
+        /// Address of the server, e.g. 192.168.1.5:7878
+        addr: String,
+        /// Seat to claim, the first free one if omitted
+        #[clap(long)]
+        seat: Option<usize>,
+    },
 }
 
-impl PrettyDisplay for Book {
-    fn to_pretty_string(&self) -> String {
-        match *self {
-            Self::LowDiamonds => "LD".blue().to_string(),
-            Self::HighDiamonds => "HD".blue().to_string(),
-            Self::LowClubs => "LC".green().to_string(),
-            Self::HighClubs => "HC".green().to_string(),
-            Self::LowHearts => "LH".red().to_string(),
-            Self::HighHearts => "HH".red().to_string(),
-            Self::LowSpades => "LS".bright_black().to_string(),
-            Self::HighSpades => "HS".bright_black().to_string(),
-            Self::Eights => "E".purple().to_string(),
+fn main() {
+    let args = Args::parse();
+    match args.mode {
+        Some(Mode::Replay { ref path }) => return replay(path),
+        Some(Mode::Connect { ref addr, seat }) => return connect(addr, seat),
+        _ => {}
+    }
+    let rules = match load_rules(&args) {
+        Ok(rules) => rules,
+        Err(err) => {
+            println!("Error: {err}");
+            return;
         }
+    };
+    match args.mode {
+        Some(Mode::Simulate {
+            deals,
+            ref strategies,
+        }) => simulate(rules, strategies, deals, args.seed),
+        Some(Mode::Serve { port, humans }) => serve(&args, &rules, port, humans),
+        _ => play(&args, &rules),
     }
 }
 
-impl Fish {
-    fn init(num_humans: u8) -> Self {
-        let num_teams = 2;
-        let num_players: usize = 6;
-        let num_cards: usize = 54;
-
-        // Instantiate deck and shuffle
-        let mut deck = Vec::new();
-        for num in 0..num_cards {
-            deck.push(Card { num: num as u8 })
-        }
-        let mut rng = rng();
-        deck.shuffle(&mut rng);
-
-        // Instantiate teams
-        let mut teams = vec![];
-        for _ in 0..num_teams {
-            teams.push(Team { books: vec![] })
-        }
-
-        // Instantiate players (humans and bots)
-        let mut bot_idxs: Vec<usize> = (0..num_players).collect();
-        deck.shuffle(&mut rng);
-        for _ in 0..num_humans {
-            bot_idxs.pop();
-        }
-
-        let mut players = vec![];
-        for idx in 0..num_players {
-            let cards = deck.drain(0..num_cards / num_players).collect();
-            let is_bot = bot_idxs.contains(&idx);
-            players.push(Player { idx, cards, is_bot })
-        }
-
-        Fish {
-            teams: Rc::new(RefCell::new(teams)),
-            players: Rc::new(RefCell::new(players)),
-            curr_player: Rc::new(RefCell::new(rng.random_range(0..num_players))),
-
-            num_humans: Rc::new(RefCell::new(num_humans)),
-            num_players: Rc::new(RefCell::new(num_players)),
-            num_cards: Rc::new(RefCell::new(num_cards)),
-        }
+// The rules file if one is given, then any rule set by a flag
+// Accept the names `strategy::by_name` knows, with any settings
+fn strategy_name(name: &str) -> Result<String, String> {
+    match strategy::by_name(name) {
+        Some(_) => Ok(name.to_string()),
+        None => Err(format!(
+            "expected one of {}, e.g. ismcts:iterations=200,time=500",
+            strategy::STRATEGIES.join(", ")
+        )),
     }
+}
 
-    fn reset(&self) {
-        let new_game: Fish = Fish::init(*self.num_humans.borrow());
-        *self.teams.borrow_mut() = new_game.teams.take();
-        *self.players.borrow_mut() = new_game.players.take();
-        *self.curr_player.borrow_mut() = new_game.curr_player.take();
-        *self.num_players.borrow_mut() = new_game.num_players.take();
+fn load_rules(args: &Args) -> Result<Rules, Box<dyn std::error::Error>> {
+    let mut rules = match args.rules {
+        Some(ref path) => Rules::from_toml(&std::fs::read_to_string(path)?)?,
+        None => Rules::default(),
+    };
+    if let Some(num_players) = args.players {
+        rules.num_players = num_players;
     }
-
-    fn handle_ask(&self, askee_idx: usize, card: &Card) -> Result<Ask, AskError> {
-        let asker_idx = *self.curr_player.borrow();
-        if self.players.borrow()[asker_idx].is_bot {
-            return Err(AskError::BotTurn);
-        }
-        self.ask(askee_idx, card)
+    if let Some(deck) = args.deck {
+        rules.deck = deck;
     }
-
-    fn ask(&self, askee_idx: usize, card: &Card) -> Result<Ask, AskError> {
-        // 1. The player must ask a player from the opposing team
-        // 2. The player must hold a card that is part of the requested book
-        // 3. The player may not ask for a card they already hold
-
-        let asker_idx = *self.curr_player.borrow();
-        if askee_idx >= *self.num_players.borrow() {
-            return Err(AskError::PlayerNotFound);
-        }
-        if askee_idx % 2 == asker_idx % 2 {
-            return Err(AskError::SameTeam);
-        }
-
-        // Get the asker and askee
-        let mut players = self.players.borrow_mut();
-        let (a, b) = players.split_at_mut(std::cmp::max(asker_idx, askee_idx));
-        let (asker, askee) = if askee_idx < asker_idx {
-            (&mut b[0], &mut a[askee_idx])
-        } else {
-            (&mut a[asker_idx], &mut b[0])
-        };
-
-        if !asker.cards.iter().any(|c| c.book() == card.book()) {
-            return Err(AskError::InvalidBook);
-        }
-        if asker.cards.contains(card) {
-            return Err(AskError::AlreadyOwnCard);
-        }
-
-        // Check if askee has the requested card
-        // If so, move it to the asker's card list
-        let outcome = {
-            if let Some(index) = askee.cards.iter().position(|c| *c == *card) {
-                let item = askee.cards.remove(index);
-                asker.cards.push(item);
-                AskOutcome::Success
-            } else {
-                *self.curr_player.borrow_mut() = askee_idx;
-                AskOutcome::Failure
-            }
-        };
-
-        Ok(Ask {
-            asker: asker_idx,
-            askee: askee_idx,
-            card: *card,
-            outcome,
-        })
+    if args.no_eights {
+        rules.eights = false;
     }
-
-    fn handle_next(&self) -> Result<Ask, NextError> {
-        let asker_idx = *self.curr_player.borrow();
-        let num_players = *self.num_players.borrow();
-
-        if !self.players.borrow()[asker_idx].is_bot {
-            return Err(NextError::HumanTurn);
-        }
-
-        // Randomly ask a user for a card
-        loop {
-            let rand_user = rand::rng().random_range(0..num_players);
-            let rand_card = Card {
-                num: rand::rng().random_range(0..54),
-            };
-            match self.ask(rand_user, &rand_card) {
-                Ok(ask) => return Ok(ask),
-                Err(_) => continue,
-            }
-        }
+    if let Some(failed_declaration) = args.failed_declaration {
+        rules.failed_declaration = failed_declaration;
     }
-
-    fn handle_declaration(&self, declarer_idx: usize, book: Book) -> Declare {
-        let mut players = self.players.borrow_mut();
-        let mut good_declaration: bool = true;
-        let mut actual_cards = HashMap::new();
-
-        for (i, player) in players.iter_mut().enumerate() {
-            // Remove all cards of that book from the player
-            let mut removed_cards = HashSet::new();
-            player.cards.retain(|card| {
-                if card.book() == book {
-                    removed_cards.insert(*card);
-                    false
-                } else {
-                    true
-                }
-            });
-
-            // Check teammates
-            if i % 2 == declarer_idx % 2 {
-                println!("Player {i} has: ");
-                let guessed_cards: HashSet<Card> = Fish::get_cards().into_iter().collect();
-                if removed_cards != guessed_cards {
-                    good_declaration = false;
-                }
-            }
-
-            actual_cards.insert(i, removed_cards);
-        }
-
-        let mut teams = self.teams.borrow_mut();
-
-        if good_declaration {
-            teams[declarer_idx % 2].books.push(book);
-            return Declare {
-                book,
-                actual_cards,
-                outcome: DeclareOutcome::Success,
-            };
-        }
-
-        teams[(declarer_idx + 1) % 2].books.push(book);
-        Declare {
-            book,
-            actual_cards,
-            outcome: DeclareOutcome::Failure,
-        }
+    if let Some(after_declaration) = args.after_declaration {
+        rules.after_declaration = after_declaration;
     }
+    rules.validate()?;
+    Ok(rules)
+}
 
-    fn check_game_end(&self) -> bool {
-        for p in self.players.borrow().iter() {
-            if p.cards.is_empty() {
-                self.reset();
-                break;
+fn play(args: &Args, rules: &Rules) {
+    let (game, engine) = match args.load {
+        Some(ref path) => match load(path) {
+            Ok(loaded) => loaded,
+            Err(err) => {
+                println!("Error: {err}");
+                return;
             }
-        }
-        false
-    }
-
-    // Helpers
-    fn get_hand(&self, idx: usize) -> Vec<Card> {
-        self.players.borrow()[idx].cards.clone()
-    }
-
-    fn curr_player(&self) -> usize {
-        *self.curr_player.borrow()
-    }
-
-    fn num_humans(&self) -> usize {
-        *self.num_humans.borrow() as usize
-    }
-
-    fn num_bots(&self) -> usize {
-        self.num_players() - self.num_humans()
-    }
-
-    fn is_bot(&self, idx: usize) -> bool {
-        self.players.borrow()[idx].is_bot
-    }
-
-    fn num_players(&self) -> usize {
-        *self.num_players.borrow()
-    }
-
-    fn num_cards(&self) -> usize {
-        *self.num_cards.borrow()
-    }
-
-    fn get_cards() -> Vec<Card> {
-        io::stdout().flush().unwrap();
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
-        match input
-            .split_whitespace()
-            .map(|s| s.parse::<Card>())
-            .collect()
-        {
-            Ok(cards) => cards,
-            _ => {
-                println!("Invalid input");
-                Fish::get_cards()
+        },
+        None => {
+            let seed = args.seed.unwrap_or_else(rand::random);
+            let game = Fish::init(rules, args.num_humans, seed);
+            for seat in (0..game.num_players()).filter(|seat| game.is_bot(*seat)) {
+                game.set_strategy(seat, strategy::by_name(&args.bot));
             }
+            let engine = Engine::init(&game);
+            (game, engine)
         }
-    }
-}
-
-#[derive(Parser)]
-struct Args {
-    #[clap(required = false, long, default_value = "0")]
-    num_humans: u8,
-}
-
-fn main() {
-    let args = Args::parse();
-    let game = Fish::init(args.num_humans);
+    };
     let g = &game;
-
-    let engine = Engine::init(g);
     let e = &engine;
-    // e.register_hand(0, &g.get_hand(0));
 
     let printer = Printer {
         use_color: Rc::new(RefCell::new(true)),
     };
     let p = &printer;
 
+    let hot_seat = HotSeat::new(args.hot_seat, g);
+    let h = &hot_seat;
+
+    // Whether the engine took each move of the game, as only those can be
+    // taken back from it
+    let learned = RefCell::new(vec![true; g.num_events()]);
+    let l = &learned;
+
     // Create the repl
     let mut repl = Repl::builder()
         .with_hints(false)
@@ -375,6 +204,9 @@ fn main() {
                         g.num_humans()
                     );
 
+                    println!("Seed: {}", g.seed());
+                    println!("Rules: {}", g.rules());
+
                     println!("It is {}'s turn", 
                         p.print_player(g.curr_player(), g), 
                     );
@@ -383,7 +215,11 @@ fn main() {
                         println!("{} [{}]: {}", 
                             p.print_player(i, g),
                             if g.is_bot(i) { "Bot" } else { "Player" },
-                            p.print_hand(i, g));
+                            if h.may_see(i) {
+                                p.print_hand(i, g)
+                            } else {
+                                format!("{} cards", g.get_hand(i).len())
+                            });
                     }
 
                     Ok(CommandStatus::Done)
@@ -408,7 +244,17 @@ fn main() {
                             }
 
                             // Engine
-                            e.update_constraints(Event::Ask(ask));
+                            learn(e, l, &Event::Ask(ask));
+                            if !g.is_over() && g.get_hand(g.curr_player()).is_empty() {
+                                println!("{} is out of cards and must pass the turn (t)", p.print_player(g.curr_player(), g));
+                            }
+
+                            if g.is_over() {
+                                print!("{}", p.print_summary(g));
+                            }
+                        },
+                        Err(AskError::GameOver) => {
+                            println!("Error: The game is over!");
                         },
                         Err(AskError::BotTurn) => {
                             println!("Error: It is a bot's turn!");
@@ -425,76 +271,712 @@ fn main() {
                         Err(AskError::AlreadyOwnCard) => {
                             println!("Error: You have the card!");
                         },
+                        Err(AskError::NoCards) => {
+                            println!("Error: That player has no cards left!");
+                        },
                     }
-                    g.check_game_end();
+                    hand_over(h, g, p);
                     Ok(CommandStatus::Done)
                 }
             },
         )
+        .add("t", command! {
+            "Pass the turn to the next teammate with cards", () => || {
+                pass(g, e, l, p, None);
+                hand_over(h, g, p);
+                Ok(CommandStatus::Done)
+            }
+        })
+        .add("t", command! {
+            "Pass the turn to a player (t 2)", (receiver: usize) => |receiver| {
+                pass(g, e, l, p, Some(receiver));
+                hand_over(h, g, p);
+                Ok(CommandStatus::Done)
+            }
+        })
         .add("c", command ! {
             "Constraints", () => || {
+                if let Err(err) = e.check_consistency() {
+                    println!("Error: {err}");
+                }
                 println!("{}", p.print_constraints(e, g));
                 Ok(CommandStatus::Done)
             }
         })
+        .add("c", command ! {
+            "Constraints known to a player (c 3)", (seat: usize) => |seat| {
+                if seat >= g.num_players() {
+                    println!("Error: That player does not exist!");
+                    return Ok(CommandStatus::Done);
+                }
+                if !h.may_see(seat) {
+                    println!("Error: You may only look at your own seat!");
+                    return Ok(CommandStatus::Done);
+                }
+                println!("{}", p.print_constraints(&g.perspective(seat), g));
+                Ok(CommandStatus::Done)
+            }
+        })
+        .add("e", command ! {
+            "Explain what is known about a player and a card (e 3 KS)", (seat: usize, card: Card) => |seat, card| {
+                if seat >= g.num_players() {
+                    println!("Error: That player does not exist!");
+                    return Ok(CommandStatus::Done);
+                }
+                print!("{}", p.print_explanation(&e.explain(card, seat), e, g));
+                Ok(CommandStatus::Done)
+            }
+        })
+        .add("p", command ! {
+            "Probabilities", () => || {
+                print!("{}", p.print_probabilities(e, g, &mut rng()));
+                Ok(CommandStatus::Done)
+            }
+        })
+        .add("p", command ! {
+            "Probabilities known to a player (p 3)", (seat: usize) => |seat| {
+                if seat >= g.num_players() {
+                    println!("Error: That player does not exist!");
+                    return Ok(CommandStatus::Done);
+                }
+                if !h.may_see(seat) {
+                    println!("Error: You may only look at your own seat!");
+                    return Ok(CommandStatus::Done);
+                }
+                print!("{}", p.print_probabilities(&g.perspective(seat), g, &mut rng()));
+                Ok(CommandStatus::Done)
+            }
+        })
         .add(
             "n",
             command! { "Next",
                 () => || {
                     match g.handle_next() {
-                        Ok(ask @ Ask { asker, askee, card, outcome }) => {
+                        Ok(event) => {
                             // Printer
-                            let response = match outcome { AskOutcome::Success => "YES", AskOutcome::Failure => "NO" };
-                            println!("{} asked {} for {} and received {response}.",
-                                p.print_player(asker, g),
-                                p.print_player(askee, g),
-                                p.to_pretty_string(&card),
-                            );
+                            println!("{}.", p.print_event(&event));
 
                             // Engine
-                            e.update_constraints(Event::Ask(ask));
+                            learn(e, l, &event);
+
+                            if g.is_over() {
+                                print!("{}", p.print_summary(g));
+                            }
                         },
+                        Err(NextError::GameOver) => println!("Error: The game is over!"),
                         Err(NextError::HumanTurn) => println!("Error: It's a human's turn!"),
+                        Err(NextError::IllegalAction(action)) => {
+                            println!("Error: The bot tried an illegal move: {action:?}");
+                        },
                     }
-                    g.check_game_end();
+                    hand_over(h, g, p);
                     Ok(CommandStatus::Done)
                 }
             },
         )
         .add(
             "d",
-            command! {
-                "Declare (d lh)", (book: Book) => |book| {
-                    // Printer
-                    let declare = g.handle_declaration(*g.curr_player.borrow(), book);
-                    match declare.outcome {
-                        DeclareOutcome::Success => {
-                            println!("Successfully declared {book:?}");
+            Command {
+                description: "Declare, naming who holds each card (d lh 0:2H,3H 2:4H 4:5H,6H,7H)".into(),
+                args_info: vec!["book:Book".into(), "claims:seat:cards...".into()],
+                handler: Box::new(|args| {
+                    let Some((book, claims)) = args.split_first() else {
+                        return Err(ArgsError::WrongNumberOfArguments { got: 0, expected: 1 }.into());
+                    };
+                    let book = g.deck().book(book).map_err(|err| ArgsError::WrongArgumentValue {
+                        argument: book.to_string(),
+                        error: err.into(),
+                    })?;
+                    let mut claimed_cards = HashMap::new();
+                    for claim in claims {
+                        let Some((seat, cards)) = parse_claim(claim) else {
+                            return Err(ArgsError::WrongArgumentValue {
+                                argument: claim.to_string(),
+                                error: anyhow!("expected seat:cards"),
+                            }.into());
+                        };
+                        *claimed_cards.entry(seat).or_default() |= cards;
+                    }
+
+                    match g.handle_declare(book, claimed_cards) {
+                        Ok(declare) => {
+                            // Printer
+                            match declare.outcome {
+                                DeclareOutcome::Success => {
+                                    println!("Successfully declared {book:?}");
+                                },
+                                DeclareOutcome::Failure => {
+                                    println!("Did not successfully declare {book:?}");
+                                }
+                            }
+
+                            // Engine
+                            learn(e, l, &Event::Declare(declare));
+                            if !g.is_over() && g.get_hand(g.curr_player()).is_empty() {
+                                println!("{} is out of cards and must pass the turn (t)", p.print_player(g.curr_player(), g));
+                            }
+
+                            if g.is_over() {
+                                print!("{}", p.print_summary(g));
+                            }
+                        },
+                        Err(DeclareError::GameOver) => println!("Error: The game is over!"),
+                        Err(DeclareError::BotTurn) => println!("Error: It is a bot's turn!"),
+                        Err(DeclareError::PlayerNotFound) => {
+                            println!("Error: That player does not exist!");
                         },
-                        DeclareOutcome::Failure => {
-                            println!("Did not successfully declare {book:?}");
+                        Err(DeclareError::AlreadyDeclared) => {
+                            println!("Error: That book was already declared!");
+                        },
+                        Err(DeclareError::NotTeammate) => {
+                            println!("Error: You can only name players on your team!");
+                        },
+                        Err(DeclareError::WrongBook) => {
+                            println!("Error: Those cards are not all in the book!");
+                        },
+                        Err(DeclareError::Incomplete) => {
+                            println!("Error: Every card of the book must be named exactly once!");
+                        },
+                    }
+                    hand_over(h, g, p);
+                    Ok(CommandStatus::Done)
+                }),
+            },
+        )
+        .add("log", command ! {
+            "Print the game record", () => || {
+                if h.hides_record(g) {
+                    println!("Error: The record shows every hand, wait until the game is over!");
+                    return Ok(CommandStatus::Done);
+                }
+                print!("{}", g.record());
+                Ok(CommandStatus::Done)
+            }
+        })
+        .add("log", command ! {
+            "Write the game record to a file (log game.txt)", (path: PathBuf) => |path: PathBuf| {
+                if h.hides_record(g) {
+                    println!("Error: The record shows every hand, wait until the game is over!");
+                    return Ok(CommandStatus::Done);
+                }
+                match std::fs::write(&path, g.record().to_string()) {
+                    Ok(()) => println!("Wrote the record to {}", path.display()),
+                    Err(err) => println!("Error: {err}"),
+                }
+                Ok(CommandStatus::Done)
+            }
+        })
+        .add("save", command ! {
+            "Save the game to continue it later (save game.sav)", (path: PathBuf) => |path: PathBuf| {
+                save(g, &path);
+                Ok(CommandStatus::Done)
+            }
+        })
+        .add("load", command ! {
+            "Continue a saved game (load game.sav)", (path: PathBuf) => |path: PathBuf| {
+                match load(&path) {
+                    Ok((game, engine)) => {
+                        g.replace(game);
+                        e.replace(engine);
+                        h.reset(g);
+                        *l.borrow_mut() = vec![true; g.num_events()];
+                        println!("Loaded the game from {}", path.display());
+                        hand_over(h, g, p);
+                    }
+                    Err(err) => println!("Error: {err}"),
+                }
+                Ok(CommandStatus::Done)
+            }
+        })
+        .add("u", command ! {
+            "Take back the last move", () => || {
+                match g.undo() {
+                    Some(event) => {
+                        if l.borrow_mut().pop() == Some(true) {
+                            e.undo();
                         }
+                        h.take_back(g);
+                        println!("Took back: {}.", p.print_event(&event));
                     }
+                    None => println!("Error: There is no move to take back!"),
+                }
+                hand_over(h, g, p);
+                Ok(CommandStatus::Done)
+            }
+        })
+        .add("redo", command ! {
+            "Play the last move taken back again", () => || {
+                redo(g, e, l, p);
+                hand_over(h, g, p);
+                Ok(CommandStatus::Done)
+            }
+        })
+        .add("f", command ! {
+            "Same as redo", () => || {
+                redo(g, e, l, p);
+                hand_over(h, g, p);
+                Ok(CommandStatus::Done)
+            }
+        })
+        .add(
+            "reset",
+            command! {
+                "Deal a new game", () => || {
+                    g.reset();
+                    e.reset(g);
+                    h.reset(g);
+                    l.borrow_mut().clear();
+                    hand_over(h, g, p);
+                    Ok(CommandStatus::Done)
+                }
+            },
+        )
+        .build()
+        .expect("Failed to build REPL");
+
+    hand_over(h, g, p);
+    repl.run().expect("Failed to run REPL");
+    if let Some(ref path) = args.save {
+        save(g, path);
+    }
+}
+
+fn save(g: &Fish, path: &Path) {
+    match std::fs::write(path, g.save().to_string()) {
+        Ok(()) => println!("Saved the game to {}", path.display()),
+        Err(err) => println!("Error: {err}"),
+    }
+}
+
+fn load(path: &Path) -> Result<(Fish, Engine), Box<dyn std::error::Error>> {
+    let save: Save = std::fs::read_to_string(path)?.parse()?;
+    Ok(save.restore()?)
+}
+
+// When the turn reaches another human, clear the screen and wait for them,
+// then show the moves since their last turn and their hand. Moves are
+// public, so the one leaving sees how their turn ended
+fn hand_over(h: &HotSeat, g: &Fish, p: &Printer) {
+    let Some(seat) = h.next_viewer(g) else {
+        return;
+    };
+    print!("{CLEAR_SCREEN}");
+    if let Some(viewer) = h.viewer() {
+        for event in h.unseen(g, viewer) {
+            println!("{}.", p.print_event(&event));
+        }
+    }
+    println!(
+        "Pass the seat to {} and press Enter",
+        p.print_player(seat, g)
+    );
+    let _ = std::io::stdout().flush();
+    let _ = std::io::stdin().read_line(&mut String::new());
+    print!("{CLEAR_SCREEN}");
+    h.sit(seat);
+
+    for event in h.unseen(g, seat) {
+        println!("{}.", p.print_event(&event));
+    }
+    println!("It is your turn, {}", p.print_player(seat, g));
+    println!("Your hand: {}", p.print_hand(seat, g));
+}
+
+fn redo(g: &Fish, e: &Engine, l: &RefCell<Vec<bool>>, p: &Printer) {
+    match g.redo() {
+        Some(event) => {
+            println!("{}.", p.print_event(&event));
+            learn(e, l, &event);
+            if g.is_over() {
+                print!("{}", p.print_summary(g));
+            }
+        }
+        None => println!("Error: There is no move to play again!"),
+    }
+}
+
+// Teach the engine a move, noting whether it took it
+fn learn(e: &Engine, l: &RefCell<Vec<bool>>, event: &Event) {
+    let result = e.update_constraints(event);
+    if let Err(ref err) = result {
+        println!("Error: {err}");
+    }
+    l.borrow_mut().push(result.is_ok());
+}
 
-                    // Engine
-                    e.update_constraints(Event::Declare(declare));
-                    g.check_game_end();
+// ANSI escape clearing the terminal and moving the cursor home
+const CLEAR_SCREEN: &str = "\x1B[2J\x1B[H";
+
+fn pass(g: &Fish, e: &Engine, l: &RefCell<Vec<bool>>, p: &Printer, to: Option<usize>) {
+    match g.handle_pass(to) {
+        Ok(pass) => {
+            let event = Event::PassTurn(pass);
+            println!("{}.", p.print_event(&event));
+            learn(e, l, &event);
+        }
+        Err(PassError::GameOver) => println!("Error: The game is over!"),
+        Err(PassError::BotTurn) => println!("Error: It is a bot's turn!"),
+        Err(PassError::PlayerNotFound) => println!("Error: That player does not exist!"),
+        Err(PassError::HasCards) => println!("Error: You can still ask for a card!"),
+        Err(PassError::NoCards) => println!("Error: That player has no cards either!"),
+        Err(PassError::NotTeammate) => {
+            println!("Error: Pass to a teammate while one still has cards!")
+        }
+    }
+}
+
+fn serve(args: &Args, rules: &Rules, port: u16, humans: usize) {
+    let seed = args.seed.unwrap_or_else(rand::random);
+    let Some(mut server) = Server::new(Fish::init(rules, 0, seed), &args.bot, humans) else {
+        println!("Error: Only {} seats can be claimed", rules.num_players);
+        return;
+    };
+    let listener = match TcpListener::bind(("0.0.0.0", port)) {
+        Ok(listener) => listener,
+        Err(err) => {
+            println!("Error: {err}");
+            return;
+        }
+    };
+    println!("Serving seed {seed} on port {port}, waiting for {humans} player(s)");
+
+    let printer = Printer {
+        use_color: Rc::new(RefCell::new(true)),
+    };
+    let result = server.run(listener, |_, event| {
+        println!("{}.", printer.print_event(event))
+    });
+    if let Err(err) = result {
+        println!("Error: {err}");
+    }
+    print!("{}", printer.print_summary(server.game()));
+}
+
+fn connect(addr: &str, seat: Option<usize>) {
+    let stream = match TcpStream::connect(addr) {
+        Ok(stream) => stream,
+        Err(err) => {
+            println!("Error: {err}");
+            return;
+        }
+    };
+    let table = Arc::new(Mutex::new(Table::default()));
+    match stream.try_clone() {
+        Ok(reader) => {
+            let table = Arc::clone(&table);
+            thread::spawn(move || {
+                let p = Printer {
+                    use_color: Rc::new(RefCell::new(true)),
+                };
+                client::listen(reader, &table, |table, message| show(&p, table, message));
+                println!("The server closed the connection");
+            });
+        }
+        Err(err) => {
+            println!("Error: {err}");
+            return;
+        }
+    }
+
+    let writer = RefCell::new(stream);
+    let send = |line: String| {
+        if let Err(err) = writeln!(writer.borrow_mut(), "{line}") {
+            println!("Error: {err}");
+        }
+    };
+    match seat {
+        Some(seat) => send(format!("seat {seat}")),
+        None => send("seat".to_string()),
+    }
+
+    let printer = Printer {
+        use_color: Rc::new(RefCell::new(true)),
+    };
+    let p = &printer;
+    let table = &table;
+    let send = &send;
+
+    let mut repl = Repl::builder()
+        .with_hints(false)
+        .add(
+            "i",
+            command! { "Info", () => || {
+                    let table = table.lock().unwrap();
+                    println!("Rules: {}", table.rules);
+                    match table.seat {
+                        Some(seat) => println!("You are {}", p.print_seat(seat)),
+                        None => println!("You have no seat"),
+                    }
+                    if let Some(turn) = table.turn {
+                        println!("It is {}'s turn", p.print_seat(turn));
+                    }
+                    println!("Your hand: {}", p.to_pretty_string(&table.hand));
+                    for (seat, size) in table.sizes.iter().enumerate() {
+                        println!("{}: {size} cards", p.print_seat(seat));
+                    }
                     Ok(CommandStatus::Done)
                 }
             },
         )
         .add(
-            "r",
+            "a",
             command! {
-                "Reset the game", () => || {
-                    g.reset();
-                    e.reset(g);
+                "Ask a player for a card", (askee: usize, card: Card) => |askee, card| {
+                    send(format!("ask {askee} {card}"));
                     Ok(CommandStatus::Done)
                 }
             },
         )
+        .add(
+            "d",
+            Command {
+                description: "Declare, naming who holds each card (d lh 0:2H,3H 2:4H 4:5H,6H,7H)"
+                    .into(),
+                args_info: vec!["book:Book".into(), "claims:seat:cards...".into()],
+                handler: Box::new(|args| {
+                    let Some((book, claims)) = args.split_first() else {
+                        return Err(ArgsError::WrongNumberOfArguments {
+                            got: 0,
+                            expected: 1,
+                        }
+                        .into());
+                    };
+                    let deck = table.lock().unwrap().deck();
+                    let book = deck
+                        .book(book)
+                        .map_err(|err| ArgsError::WrongArgumentValue {
+                            argument: book.to_string(),
+                            error: err.into(),
+                        })?;
+                    let mut line = format!("declare {book}");
+                    for claim in claims {
+                        let Some((seat, cards)) = parse_claim(claim) else {
+                            return Err(ArgsError::WrongArgumentValue {
+                                argument: claim.to_string(),
+                                error: anyhow!("expected seat:cards"),
+                            }
+                            .into());
+                        };
+                        line += &format!(" {seat}:{cards}");
+                    }
+                    send(line);
+                    Ok(CommandStatus::Done)
+                }),
+            },
+        )
+        .add(
+            "t",
+            command! {
+                "Pass the turn to the next teammate with cards", () => || {
+                    send("pass".to_string());
+                    Ok(CommandStatus::Done)
+                }
+            },
+        )
+        .add(
+            "t",
+            command! {
+                "Pass the turn to a player (t 2)", (receiver: usize) => |receiver| {
+                    send(format!("pass {receiver}"));
+                    Ok(CommandStatus::Done)
+                }
+            },
+        )
+        .add(
+            "s",
+            command! {
+                "Take a free seat (s 3)", (seat: usize) => |seat| {
+                    send(format!("seat {seat}"));
+                    Ok(CommandStatus::Done)
+                }
+            },
+        )
+        .add(
+            "log",
+            command! {
+                "Print every move seen since connecting", () => || {
+                    for event in table.lock().unwrap().log.iter() {
+                        println!("{}.", p.print_event(event));
+                    }
+                    Ok(CommandStatus::Done)
+                }
+            },
+        )
+        .build()
+        .expect("Failed to build REPL");
+
+    repl.run().expect("Failed to run REPL");
+    send("quit".to_string());
+}
+
+// Print what the server sends, with the table as it was before the message
+fn show(p: &Printer, table: &Table, message: &ServerMessage) {
+    match message {
+        ServerMessage::Rules(rules) => println!("Rules: {rules}"),
+        ServerMessage::Free(seats) => {
+            let seats: Vec<String> = seats.iter().map(|seat| seat.to_string()).collect();
+            println!("Free seats: {}", seats.join(" "));
+        }
+        ServerMessage::Seat(seat) => println!("You are {}", p.print_seat(*seat)),
+        ServerMessage::Start => println!("The game has started"),
+        ServerMessage::Hand(hand) if *hand != table.hand || table.turn.is_none() => {
+            println!("Your hand: {}", p.to_pretty_string(hand));
+        }
+        ServerMessage::Turn(seat) if table.turn != Some(*seat) => {
+            let you = if table.seat == Some(*seat) {
+                " (you)"
+            } else {
+                ""
+            };
+            println!("It is {}'s turn{you}", p.print_seat(*seat));
+        }
+        ServerMessage::Event(event) => println!("{}.", p.print_event(event)),
+        ServerMessage::Error(err) => println!("Error: {err}"),
+        ServerMessage::Over(books) => match books[0].cmp(&books[1]) {
+            std::cmp::Ordering::Equal => println!("The game is tied at {} books", books[0]),
+            _ => {
+                let team = usize::from(books[1] > books[0]);
+                println!(
+                    "Team {team} wins {} books to {}",
+                    books[team],
+                    books[1 - team]
+                );
+            }
+        },
+        _ => {}
+    }
+}
+
+fn replay(path: &Path) {
+    let record = match std::fs::read_to_string(path) {
+        Ok(text) => text.parse::<Record>(),
+        Err(err) => {
+            println!("Error: {err}");
+            return;
+        }
+    };
+    let replay = match record.and_then(Replay::new) {
+        Ok(replay) => replay,
+        Err(err) => {
+            println!("Error: {err}");
+            return;
+        }
+    };
+    let r = &replay;
+
+    let printer = Printer {
+        use_color: Rc::new(RefCell::new(true)),
+    };
+    let p = &printer;
+
+    let mut repl = Repl::builder()
+        .with_hints(false)
+        .add("i", command! {
+            "Info", () => || {
+                let g = r.game();
+                println!("Move {} of {}", r.position(), r.len());
+                println!("It is {}'s turn", p.print_player(g.curr_player(), g));
+                for i in 0..g.num_players() {
+                    println!("{}: {}", p.print_player(i, g), p.print_hand(i, g));
+                }
+                Ok(CommandStatus::Done)
+            }
+        })
+        .add("n", command! {
+            "Play the next move", () => || {
+                match r.step() {
+                    Ok(Some(event)) => println!("{}. {}.", r.position(), p.print_event(&event)),
+                    Ok(None) => println!("The record has no more moves"),
+                    Err(err) => println!("Error: {err}"),
+                }
+                Ok(CommandStatus::Done)
+            }
+        })
+        .add("g", command! {
+            "Go to the position after a move (g 17)", (n: usize) => |n| {
+                if let Err(err) = r.seek(n) {
+                    println!("Error: {err}");
+                }
+                println!("Move {} of {}", r.position(), r.len());
+                Ok(CommandStatus::Done)
+            }
+        })
+        .add("c", command! {
+            "Constraints", () => || {
+                println!("{}", p.print_constraints(r.engine(), r.game()));
+                Ok(CommandStatus::Done)
+            }
+        })
+        .add("c", command! {
+            "Constraints known to a player (c 3)", (seat: usize) => |seat| {
+                let g = r.game();
+                if seat >= g.num_players() {
+                    println!("Error: That player does not exist!");
+                    return Ok(CommandStatus::Done);
+                }
+                println!("{}", p.print_constraints(&g.perspective(seat), g));
+                Ok(CommandStatus::Done)
+            }
+        })
+        .add("e", command! {
+            "Explain what is known about a player and a card (e 3 KS)", (seat: usize, card: Card) => |seat, card| {
+                let (g, e) = (r.game(), r.engine());
+                if seat >= g.num_players() {
+                    println!("Error: That player does not exist!");
+                    return Ok(CommandStatus::Done);
+                }
+                print!("{}", p.print_explanation(&e.explain(card, seat), e, g));
+                Ok(CommandStatus::Done)
+            }
+        })
+        .add("p", command! {
+            "Probabilities", () => || {
+                print!("{}", p.print_probabilities(r.engine(), r.game(), &mut rng()));
+                Ok(CommandStatus::Done)
+            }
+        })
         .build()
         .expect("Failed to build REPL");
 
     repl.run().expect("Failed to run REPL");
 }
+
+fn simulate(rules: Rules, strategies: &[String], deals: usize, seed: Option<u64>) {
+    let seed = seed.unwrap_or_else(rand::random);
+    let Some(tournament) = Tournament::new(rules, strategies.to_vec(), deals, seed) else {
+        println!("Error: Name at least two known strategies");
+        return;
+    };
+    println!("Playing {} games from seed {seed}", tournament.num_games());
+
+    let mut n = 0;
+    let standings = tournament.run(|names, result| {
+        n += 1;
+        println!(
+            "Game {n}: {} {} - {} {} in {} moves{}",
+            names[0],
+            result.books[0],
+            result.books[1],
+            names[1],
+            result.moves,
+            if result.finished { "" } else { " (unfinished)" },
+        );
+    });
+
+    println!();
+    for s in standings.iter() {
+        let (low, high) = s.confidence_interval();
+        println!(
+            "{}: {} wins, {} draws, {} losses, {:.2} books per game, win rate {:.1}% (95% CI {:.1}%-{:.1}%){}",
+            s.name,
+            s.wins,
+            s.draws,
+            s.losses,
+            s.books as f64 / s.games.max(1) as f64,
+            s.win_rate() * 100.0,
+            low * 100.0,
+            high * 100.0,
+            if s.unfinished > 0 { format!(", {} unfinished", s.unfinished) } else { String::new() },
+        );
+    }
+}
@@ -0,0 +1,150 @@
+// Bipartite matching between hand slots (left) and cards (right).
+// A deal is consistent iff every slot can be given a distinct card from its
+// domain such that every card in play is used exactly once, i.e. the graph
+// has a perfect matching.
+use std::vec::Vec;
+
+#[derive(Debug, Clone)]
+pub struct Matching {
+    pub left: Vec<Option<usize>>,
+    pub right: Vec<Option<usize>>,
+}
+
+impl Matching {
+    pub fn is_perfect(&self) -> bool {
+        self.left.iter().all(Option::is_some) && self.right.iter().all(Option::is_some)
+    }
+}
+
+/// Maximum matching of `adj` (left -> right neighbours) using augmenting paths
+pub fn max_matching(adj: &[Vec<usize>], num_right: usize) -> Matching {
+    let mut matching = Matching {
+        left: vec![None; adj.len()],
+        right: vec![None; num_right],
+    };
+    repair(adj, &mut matching);
+    matching
+}
+
+/// Extend a partial matching by augmenting from every unmatched left node.
+/// Existing pairs are kept unless an augmenting path reroutes them
+pub fn repair(adj: &[Vec<usize>], matching: &mut Matching) {
+    let num_right = matching.right.len();
+    for u in 0..adj.len() {
+        if matching.left[u].is_some() {
+            continue;
+        }
+        let mut visited = vec![false; num_right];
+        augment(u, adj, matching, &mut visited);
+    }
+}
+
+fn augment(u: usize, adj: &[Vec<usize>], matching: &mut Matching, visited: &mut [bool]) -> bool {
+    for &v in adj[u].iter() {
+        if visited[v] {
+            continue;
+        }
+        visited[v] = true;
+        let free = match matching.right[v] {
+            None => true,
+            Some(w) => augment(w, adj, matching, visited),
+        };
+        if free {
+            matching.left[u] = Some(v);
+            matching.right[v] = Some(u);
+            return true;
+        }
+    }
+    false
+}
+
+/// Edges of `adj` that belong to at least one perfect matching.
+/// Expects `matching` to be perfect. An unmatched edge (u, v) can be swapped in
+/// iff it lies on an alternating cycle, i.e. u and v share a strongly connected
+/// component once matched edges point right -> left and the others left -> right.
+pub fn feasible_edges(adj: &[Vec<usize>], matching: &Matching) -> Vec<Vec<usize>> {
+    let n = adj.len();
+    let m = matching.right.len();
+
+    let mut graph: Vec<Vec<usize>> = vec![vec![]; n + m];
+    for (u, vs) in adj.iter().enumerate() {
+        for &v in vs.iter() {
+            if matching.left[u] == Some(v) {
+                graph[n + v].push(u);
+            } else {
+                graph[u].push(n + v);
+            }
+        }
+    }
+
+    let comp = strongly_connected_components(&graph);
+    adj.iter()
+        .enumerate()
+        .map(|(u, vs)| {
+            vs.iter()
+                .copied()
+                .filter(|&v| matching.left[u] == Some(v) || comp[u] == comp[n + v])
+                .collect()
+        })
+        .collect()
+}
+
+// Tarjan's algorithm, returns the component id of every node
+fn strongly_connected_components(graph: &[Vec<usize>]) -> Vec<usize> {
+    struct State {
+        index: Vec<Option<usize>>,
+        low: Vec<usize>,
+        on_stack: Vec<bool>,
+        stack: Vec<usize>,
+        comp: Vec<usize>,
+        next_index: usize,
+        next_comp: usize,
+    }
+
+    fn visit(u: usize, graph: &[Vec<usize>], s: &mut State) {
+        s.index[u] = Some(s.next_index);
+        s.low[u] = s.next_index;
+        s.next_index += 1;
+        s.stack.push(u);
+        s.on_stack[u] = true;
+
+        for &v in graph[u].iter() {
+            match s.index[v] {
+                None => {
+                    visit(v, graph, s);
+                    s.low[u] = s.low[u].min(s.low[v]);
+                }
+                Some(idx) if s.on_stack[v] => s.low[u] = s.low[u].min(idx),
+                _ => {}
+            }
+        }
+
+        if Some(s.low[u]) == s.index[u] {
+            while let Some(w) = s.stack.pop() {
+                s.on_stack[w] = false;
+                s.comp[w] = s.next_comp;
+                if w == u {
+                    break;
+                }
+            }
+            s.next_comp += 1;
+        }
+    }
+
+    let n = graph.len();
+    let mut state = State {
+        index: vec![None; n],
+        low: vec![0; n],
+        on_stack: vec![false; n],
+        stack: vec![],
+        comp: vec![0; n],
+        next_index: 0,
+        next_comp: 0,
+    };
+    for u in 0..n {
+        if state.index[u].is_none() {
+            visit(u, graph, &mut state);
+        }
+    }
+    state.comp
+}
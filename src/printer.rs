@@ -1,12 +1,17 @@
-use crate::card::{Card, DisplayCard, Suit};
-use crate::engine::{Constraint, Engine, Slot};
-use crate::{Fish, Player};
+use crate::card::{Card, CardSet, DisplayCard, Suit};
+use crate::deck::Book;
+use crate::engine::{Constraint, Engine, Explanation, Fact, ProbDist, Reason, Slot};
+use crate::{Ask, AskOutcome, Declare, DeclareOutcome, Event, Fish, PassTurn, Player, Summary};
 use colored::Colorize;
+use rand::Rng;
 use std::cell::RefCell;
 use std::fmt::Debug;
 use std::fmt::Write as FmtWrite;
 use std::rc::Rc;
 
+// Deals sampled when estimating who holds each card
+const NUM_SAMPLES: usize = 1000;
+
 pub trait PrettyDisplay {
     fn to_pretty_string(&self) -> String;
 }
@@ -27,19 +32,27 @@ impl Printer {
 
     // Printers
     pub fn print_hand(&self, player: usize, g: &Fish) -> String {
-        let mut players = g.players.borrow_mut();
-        players[player].cards.sort();
+        let players = g.players();
         self.to_pretty_string(&players[player].cards)
     }
 
     pub fn print_player(&self, player: usize, g: &Fish) -> String {
-        let players = g.players.borrow();
+        let players = g.players();
         self.to_pretty_string(&players[player])
     }
 
+    /// A seat named like its player, for tables without a game
+    pub fn print_seat(&self, seat: usize) -> String {
+        if *self.use_color.borrow() {
+            seat_name(seat)
+        } else {
+            format!("Player {seat}")
+        }
+    }
+
     pub fn print_constraints(&self, e: &Engine, g: &Fish) -> String {
         let mut output = String::new();
-        let map = e.prune();
+        let map = e.domains();
         for (player, hand) in map.iter() {
             writeln!(output, "{}", self.print_player(*player, g)).unwrap();
             for (i, slot) in hand.iter().enumerate() {
@@ -48,6 +61,191 @@ impl Printer {
         }
         output.to_string()
     }
+
+    pub fn print_event(&self, event: &Event) -> String {
+        match event {
+            Event::Ask(Ask {
+                asker,
+                askee,
+                card,
+                outcome,
+            }) => {
+                let response = match outcome {
+                    AskOutcome::Success => "YES",
+                    AskOutcome::Failure => "NO",
+                };
+                format!(
+                    "{} asked {} for {} and received {response}",
+                    self.print_seat(*asker),
+                    self.print_seat(*askee),
+                    self.to_pretty_string(card),
+                )
+            }
+            Event::Declare(Declare { book, outcome, .. }) => {
+                let result = match outcome {
+                    DeclareOutcome::Success => "successfully",
+                    DeclareOutcome::Failure => "unsuccessfully",
+                };
+                format!("{} was declared {result}", self.to_pretty_string(book))
+            }
+            Event::PassTurn(PassTurn { passer, receiver }) => format!(
+                "{} is out of cards and passed the turn to {}",
+                self.print_seat(*passer),
+                self.print_seat(*receiver)
+            ),
+        }
+    }
+
+    /// Books won by each team, the winner and how each team played
+    pub fn print_summary(&self, g: &Fish) -> String {
+        let Summary {
+            books,
+            discarded,
+            winner,
+            num_moves,
+            stats,
+        } = g.summary();
+        let mut output = String::new();
+        match winner {
+            Some(team) => writeln!(
+                output,
+                "Team {team} wins {} books to {} after {num_moves} moves",
+                books[team].len(),
+                books[1 - team].len()
+            ),
+            None if g.is_over() => writeln!(output, "The game is tied after {num_moves} moves"),
+            None => writeln!(output, "The game is still going after {num_moves} moves"),
+        }
+        .unwrap();
+        for (team, (books, stats)) in books.iter().zip(stats.iter()).enumerate() {
+            writeln!(
+                output,
+                "Team {team}: {} books {}, {} of {} asks answered yes, {} of {} declarations successful",
+                books.len(),
+                self.to_pretty_string(books),
+                stats.successful_asks,
+                stats.asks,
+                stats.successful_declarations,
+                stats.declarations,
+            )
+            .unwrap();
+        }
+        if !discarded.is_empty() {
+            writeln!(output, "Discarded: {}", self.to_pretty_string(&discarded)).unwrap();
+        }
+        output
+    }
+
+    /// The chain of deductions behind a fact, one premise per indented line
+    pub fn print_explanation(&self, explanation: &Explanation, e: &Engine, g: &Fish) -> String {
+        let mut output = String::new();
+        self.write_explanation(&mut output, explanation, 0, e, g);
+        output
+    }
+
+    fn write_explanation(
+        &self,
+        output: &mut String,
+        explanation: &Explanation,
+        depth: usize,
+        e: &Engine,
+        g: &Fish,
+    ) {
+        let fact = match explanation.fact {
+            Fact::Holds(player, card) => format!(
+                "{} holds {}",
+                self.print_player(player, g),
+                self.to_pretty_string(&card)
+            ),
+            Fact::Lacks(player, card) => format!(
+                "{} does not hold {}",
+                self.print_player(player, g),
+                self.to_pretty_string(&card)
+            ),
+            Fact::HasBook(player, book) => format!(
+                "{} holds a card of {}",
+                self.print_player(player, g),
+                self.to_pretty_string(&book)
+            ),
+            Fact::Declared(card) => format!("{} is out of play", self.to_pretty_string(&card)),
+            Fact::Unknown(player, card) => format!(
+                "{} may hold {}",
+                self.print_player(player, g),
+                self.to_pretty_string(&card)
+            ),
+        };
+
+        let event = |n: usize| match e.event(n) {
+            Some(event) => format!("#{n} ({})", self.print_event(&event)),
+            None => format!("#{n}"),
+        };
+        let reason = match explanation.reason {
+            None => match explanation.fact {
+                Fact::Unknown(..) => "nothing rules it in or out yet".to_string(),
+                _ => "no record of why".to_string(),
+            },
+            Some(Reason::OwnHand) => "it was dealt to them".to_string(),
+            Some(Reason::Received(n)) => format!("they received it in {}", event(n)),
+            Some(Reason::Asked(n)) => format!("they asked for it in {}", event(n)),
+            Some(Reason::Denied(n)) => format!("they were asked for it in {}", event(n)),
+            Some(Reason::ShowedBook(n)) => format!("they asked for a card of it in {}", event(n)),
+            Some(Reason::Declared(n)) => format!("its book was declared in {}", event(n)),
+            Some(Reason::HeldBy(holder)) => format!("{} holds it", self.print_player(holder, g)),
+            Some(Reason::OnlyCandidate) => "every other player is ruled out".to_string(),
+            Some(Reason::LastOfBook(book)) => format!(
+                "they hold a card of {} and every other one is ruled out",
+                self.to_pretty_string(&book)
+            ),
+            Some(Reason::Passed(n)) => format!("they passed the turn in {}", event(n)),
+            Some(Reason::Counting(n)) => {
+                format!(
+                    "hand sizes leave no room for anything else after {}",
+                    event(n)
+                )
+            }
+        };
+
+        writeln!(output, "{}{fact}: {reason}", "  ".repeat(depth)).unwrap();
+        for premise in explanation.premises.iter() {
+            self.write_explanation(output, premise, depth + 1, e, g);
+        }
+    }
+
+    /// Likely holders of every card whose location is not yet certain
+    pub fn print_probabilities(&self, e: &Engine, g: &Fish, rng: &mut impl Rng) -> String {
+        let mut output = String::new();
+        let mut dists: Vec<(Card, ProbDist)> =
+            e.probabilities(NUM_SAMPLES, rng).into_iter().collect();
+        dists.sort_by_key(|(card, _)| *card);
+        for (card, dist) in dists.iter() {
+            let mut holders: Vec<(usize, f32)> = dist.iter().map(|(p, x)| (*p, *x)).collect();
+            if holders.len() < 2 {
+                continue;
+            }
+            holders.sort_by(|a, b| b.1.total_cmp(&a.1));
+            let holders = holders
+                .iter()
+                .map(|(p, x)| format!("{} ({:.0}%)", self.print_player(*p, g), x * 100.0))
+                .collect::<Vec<_>>()
+                .join(", ");
+            writeln!(output, "{}: {holders}", self.to_pretty_string(card)).unwrap();
+        }
+        output
+    }
+}
+
+// Books of a single suit take its color, mixed ones are purple
+impl PrettyDisplay for Book {
+    fn to_pretty_string(&self) -> String {
+        let name = self.to_string();
+        match self.suit() {
+            Some(Suit::Diamonds) => name.blue().to_string(),
+            Some(Suit::Clubs) => name.green().to_string(),
+            Some(Suit::Hearts) => name.red().to_string(),
+            Some(Suit::Spades) => name.bright_black().to_string(),
+            None => name.purple().to_string(),
+        }
+    }
 }
 
 impl PrettyDisplay for Card {
@@ -83,13 +281,24 @@ impl<T: PrettyDisplay> PrettyDisplay for Vec<T> {
     }
 }
 
+impl PrettyDisplay for CardSet {
+    fn to_pretty_string(&self) -> String {
+        self.to_vec().to_pretty_string()
+    }
+}
+
 impl PrettyDisplay for Player {
     fn to_pretty_string(&self) -> String {
-        if self.idx % 2 == 0 {
-            format!("{}", format!("Player {}", self.idx).blue())
-        } else {
-            format!("{}", format!("Player {}", self.idx).red())
-        }
+        seat_name(self.idx)
+    }
+}
+
+// Players are colored by team
+fn seat_name(seat: usize) -> String {
+    if seat.is_multiple_of(2) {
+        format!("{}", format!("Player {seat}").blue())
+    } else {
+        format!("{}", format!("Player {seat}").red())
     }
 }
 
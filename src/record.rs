@@ -0,0 +1,480 @@
+// A game record is plain text with one entry per line, much like PGN:
+//
+//   Seed 1234
+//   Rules players=6 deck=fish eights=true failed-declaration=opponents after-declaration=unchanged
+//   Seat 0 random 2♦,7♦,10♦,J♣,Q♣,A♣,3♥,8♦,BJ
+//   ...
+//   First 4
+//   Ask 4 1 2♦ Yes
+//   Declare 4 LD Success claimed 0:2♦ 4:3♦,4♦ actual 0:2♦ 4:3♦,4♦
+//   Pass 4 2
+//
+// Each seat names the strategy playing it, or Human. Records without a
+// Rules line were played under the default rules. Blank lines and
+// anything after a `#` are ignored. Declarations list only the players
+// holding cards of the book
+use crate::card::{Card, CardSet};
+use crate::deck::Deck;
+use crate::engine::Engine;
+use crate::game::{parse_claim, Ask, AskOutcome, Declare, DeclareOutcome, Event, Fish, PassTurn};
+use crate::rules::Rules;
+use crate::strategy;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// The deal and every public event of a game, enough to replay it
+#[derive(Clone, Debug)]
+pub struct Record {
+    pub rules: Rules,
+    pub seed: u64,
+    pub deal: Vec<CardSet>,
+    /// Name of the strategy playing each seat, None for a human
+    pub strategies: Vec<Option<String>>,
+    pub first_player: usize,
+    pub events: Vec<Event>,
+}
+
+/// Steps through a record, keeping the game and the public engine in the
+/// position reached after the current number of moves
+#[derive(Debug)]
+pub struct Replay {
+    record: Record,
+    game: Fish,
+    engine: Engine,
+    position: Rc<RefCell<usize>>,
+}
+
+impl Replay {
+    /// Check that every move of the record can be played, then stand at the start
+    pub fn new(record: Record) -> Result<Self, RecordError> {
+        let game = Replay::opening(&record);
+        let engine = Engine::init(&game);
+        let replay = Replay {
+            record,
+            game,
+            engine,
+            position: Rc::new(RefCell::new(0)),
+        };
+        replay.seek(replay.len())?;
+        replay.seek(0)?;
+        Ok(replay)
+    }
+
+    /// Play the next move. Returns None at the end of the record
+    pub fn step(&self) -> Result<Option<Event>, RecordError> {
+        let n = self.position();
+        let Some(event) = self.record.events.get(n).cloned() else {
+            return Ok(None);
+        };
+        let illegal = || RecordError::Illegal(n + 1, Box::new(event.clone()));
+
+        let played = self.game.replay(&event).ok_or_else(illegal)?;
+        if !same_event(&played, &event) {
+            return Err(illegal());
+        }
+        self.engine
+            .update_constraints(&played)
+            .map_err(|_| illegal())?;
+        *self.position.borrow_mut() += 1;
+        Ok(Some(played))
+    }
+
+    /// Stand at the position after the first `n` moves
+    pub fn seek(&self, n: usize) -> Result<(), RecordError> {
+        let n = n.min(self.len());
+        if n < self.position() {
+            self.game.replace(Replay::opening(&self.record));
+            self.engine.reset(&self.game);
+            *self.position.borrow_mut() = 0;
+        }
+        while self.position() < n {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    pub fn game(&self) -> &Fish {
+        &self.game
+    }
+
+    pub fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    /// The game and the public engine in the position reached
+    pub fn into_parts(self) -> (Fish, Engine) {
+        (self.game, self.engine)
+    }
+
+    /// Number of moves played so far
+    pub fn position(&self) -> usize {
+        *self.position.borrow()
+    }
+
+    /// Number of moves in the record
+    pub fn len(&self) -> usize {
+        self.record.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.record.events.is_empty()
+    }
+
+    fn opening(record: &Record) -> Fish {
+        let strategies = record
+            .strategies
+            .iter()
+            .map(|name| name.as_deref().and_then(strategy::by_name))
+            .collect();
+        Fish::from_deal(
+            &record.rules,
+            &record.deal,
+            strategies,
+            record.first_player,
+            record.seed,
+        )
+    }
+}
+
+// Declarations are compared on the players who actually hold cards
+fn same_event(a: &Event, b: &Event) -> bool {
+    let holders = |cards: &HashMap<usize, CardSet>| {
+        let mut holders: Vec<(usize, CardSet)> = cards
+            .iter()
+            .filter(|(_, cards)| !cards.is_empty())
+            .map(|(player, cards)| (*player, *cards))
+            .collect();
+        holders.sort_by_key(|(player, _)| *player);
+        holders
+    };
+    match (a, b) {
+        (Event::Ask(a), Event::Ask(b)) => {
+            a.asker == b.asker && a.askee == b.askee && a.card == b.card && a.outcome == b.outcome
+        }
+        (Event::Declare(a), Event::Declare(b)) => {
+            a.declarer == b.declarer
+                && a.book == b.book
+                && a.outcome == b.outcome
+                && holders(&a.claimed_cards) == holders(&b.claimed_cards)
+                && holders(&a.actual_cards) == holders(&b.actual_cards)
+        }
+        (Event::PassTurn(a), Event::PassTurn(b)) => {
+            a.passer == b.passer && a.receiver == b.receiver
+        }
+        _ => false,
+    }
+}
+
+fn write_assignment(
+    f: &mut std::fmt::Formatter<'_>,
+    cards: &HashMap<usize, CardSet>,
+) -> std::fmt::Result {
+    let mut players: Vec<&usize> = cards.keys().collect();
+    players.sort();
+    for player in players {
+        if !cards[player].is_empty() {
+            write!(f, " {player}:{}", cards[player])?;
+        }
+    }
+    Ok(())
+}
+
+/// One event written as a line of a record, without the line break
+pub(crate) struct EventLine<'a>(pub &'a Event);
+
+impl std::fmt::Display for EventLine<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Event::Ask(Ask {
+                asker,
+                askee,
+                card,
+                outcome,
+            }) => {
+                let outcome = match outcome {
+                    AskOutcome::Success => "Yes",
+                    AskOutcome::Failure => "No",
+                };
+                write!(f, "Ask {asker} {askee} {card} {outcome}")
+            }
+            Event::Declare(Declare {
+                declarer,
+                book,
+                claimed_cards,
+                actual_cards,
+                outcome,
+            }) => {
+                write!(f, "Declare {declarer} {book} {outcome:?} claimed")?;
+                write_assignment(f, claimed_cards)?;
+                write!(f, " actual")?;
+                write_assignment(f, actual_cards)
+            }
+            Event::PassTurn(PassTurn { passer, receiver }) => {
+                write!(f, "Pass {passer} {receiver}")
+            }
+        }
+    }
+}
+
+/// Read an event from the tokens of a record line
+pub(crate) fn parse_event(tokens: &[&str], deck: &'static Deck) -> Option<Event> {
+    match tokens {
+        ["Ask", asker, askee, card, outcome] => Some(Event::Ask(Ask {
+            asker: asker.parse().ok()?,
+            askee: askee.parse().ok()?,
+            card: card.parse::<Card>().ok()?,
+            outcome: match *outcome {
+                "Yes" => AskOutcome::Success,
+                "No" => AskOutcome::Failure,
+                _ => return None,
+            },
+        })),
+        ["Declare", declarer, book, outcome, "claimed", rest @ ..] => {
+            let split = rest.iter().position(|token| *token == "actual")?;
+            let claimed_cards = rest[..split]
+                .iter()
+                .map(|token| parse_claim(token))
+                .collect::<Option<_>>()?;
+            let actual_cards = rest[split + 1..]
+                .iter()
+                .map(|token| parse_claim(token))
+                .collect::<Option<_>>()?;
+            Some(Event::Declare(Declare {
+                declarer: declarer.parse().ok()?,
+                book: deck.book(book).ok()?,
+                claimed_cards,
+                actual_cards,
+                outcome: match *outcome {
+                    "Success" => DeclareOutcome::Success,
+                    "Failure" => DeclareOutcome::Failure,
+                    _ => return None,
+                },
+            }))
+        }
+        ["Pass", passer, receiver] => Some(Event::PassTurn(PassTurn {
+            passer: passer.parse().ok()?,
+            receiver: receiver.parse().ok()?,
+        })),
+        _ => None,
+    }
+}
+
+/// What went wrong reading or replaying a record
+#[derive(Clone, Debug)]
+pub enum RecordError {
+    /// The line with this number could not be read
+    Syntax(usize, String),
+    /// The record has no seats or no first player
+    MissingDeal,
+    /// The seats or their cards do not fit the rules
+    WrongDeal,
+    /// The move with this number does not follow from the position before it
+    Illegal(usize, Box<Event>),
+}
+
+impl std::error::Error for RecordError {}
+
+impl std::fmt::Display for RecordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordError::Syntax(line, text) => write!(f, "Line {line} cannot be read: {text}"),
+            RecordError::MissingDeal => {
+                write!(f, "The record does not say how the cards were dealt")
+            }
+            RecordError::WrongDeal => write!(f, "The deal does not fit the rules"),
+            RecordError::Illegal(n, event) => write!(f, "Move {n} cannot be played: {event:?}"),
+        }
+    }
+}
+
+impl std::fmt::Display for Record {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Seed {}", self.seed)?;
+        writeln!(f, "Rules {}", self.rules)?;
+        for (seat, (cards, strategy)) in self.deal.iter().zip(self.strategies.iter()).enumerate() {
+            let kind = strategy.as_deref().unwrap_or("Human");
+            writeln!(f, "Seat {seat} {kind} {cards}")?;
+        }
+        writeln!(f, "First {}", self.first_player)?;
+        for event in self.events.iter() {
+            writeln!(f, "{}", EventLine(event))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Record {
+    type Err = RecordError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut seed = 0;
+        let mut rules = Rules::default();
+        let mut seats: Vec<(usize, Option<String>, CardSet)> = vec![];
+        let mut first_player = None;
+        // Books are named by the deck, which the rules may still change
+        let mut event_lines = vec![];
+
+        for (i, line) in s.lines().enumerate() {
+            let text = line.split('#').next().unwrap_or_default();
+            let tokens: Vec<&str> = text.split_whitespace().collect();
+            let syntax = || RecordError::Syntax(i + 1, line.to_string());
+            match tokens[..] {
+                [] => {}
+                ["Seed", n] => seed = n.parse().map_err(|_| syntax())?,
+                ["Rules", ref rest @ ..] => rules = rest.join(" ").parse().map_err(|_| syntax())?,
+                ["Seat", seat, kind, ref cards @ ..] => {
+                    let strategy = match kind {
+                        "Human" => None,
+                        _ if strategy::by_name(kind).is_some() => Some(kind.to_string()),
+                        _ => return Err(syntax()),
+                    };
+                    let cards = cards.join(" ").parse().map_err(|_| syntax())?;
+                    seats.push((seat.parse().map_err(|_| syntax())?, strategy, cards));
+                }
+                ["First", n] => first_player = Some(n.parse().map_err(|_| syntax())?),
+                _ => event_lines.push((i, line, tokens)),
+            }
+        }
+        let events = event_lines
+            .into_iter()
+            .map(|(i, line, tokens)| {
+                parse_event(&tokens, rules.deck())
+                    .ok_or_else(|| RecordError::Syntax(i + 1, line.to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        seats.sort_by_key(|(seat, _, _)| *seat);
+        if seats.is_empty() || seats.iter().enumerate().any(|(i, (seat, _, _))| i != *seat) {
+            return Err(RecordError::MissingDeal);
+        }
+        let dealt: CardSet = seats
+            .iter()
+            .map(|(_, _, cards)| *cards)
+            .fold(CardSet::new(), |a, b| a | b);
+        let num_dealt: usize = seats.iter().map(|(_, _, cards)| cards.len()).sum();
+        if seats.len() != rules.num_players
+            || dealt != rules.deck().cards()
+            || num_dealt != dealt.len()
+        {
+            return Err(RecordError::WrongDeal);
+        }
+        let first_player = first_player.ok_or(RecordError::MissingDeal)?;
+        if first_player >= seats.len() {
+            return Err(RecordError::WrongDeal);
+        }
+        Ok(Record {
+            rules,
+            seed,
+            deal: seats.iter().map(|(_, _, cards)| *cards).collect(),
+            strategies: seats
+                .iter()
+                .map(|(_, strategy, _)| strategy.clone())
+                .collect(),
+            first_player,
+            events,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::tests::{human_table, play_at_random};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn recorded_game(num_moves: usize) -> Fish {
+        let mut rng = StdRng::seed_from_u64(2);
+        let g = human_table(6, 2);
+        for _ in 0..num_moves {
+            play_at_random(&g, &mut rng);
+        }
+        g
+    }
+
+    #[test]
+    fn replays_a_record_read_back_from_text() {
+        let g = recorded_game(40);
+        let text = g.record().to_string();
+        let record: Record = text.parse().unwrap();
+        assert_eq!(record.to_string(), text);
+
+        let replay = Replay::new(record).unwrap();
+        replay.seek(replay.len()).unwrap();
+        for seat in 0..g.num_players() {
+            assert_eq!(replay.game().get_hand(seat), g.get_hand(seat));
+        }
+        assert_eq!(replay.game().curr_player(), g.curr_player());
+    }
+
+    // A table of each size and deck, with each way of settling declarations
+    fn rule_sets() -> Vec<Rules> {
+        let text = [
+            "players=6 deck=fish eights=true failed-declaration=opponents after-declaration=unchanged",
+            "players=4 deck=fish eights=false failed-declaration=discarded after-declaration=declarer",
+            "players=8 deck=fish eights=true failed-declaration=opponents after-declaration=outcome",
+            "players=4 deck=go-fish eights=true failed-declaration=discarded after-declaration=outcome",
+            "players=6 deck=go-fish eights=false failed-declaration=opponents after-declaration=declarer",
+        ];
+        text.iter().map(|rules| rules.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn every_rule_set_plays_to_the_end_and_reads_back() {
+        for (seed, rules) in rule_sets().into_iter().enumerate() {
+            let mut rng = StdRng::seed_from_u64(seed as u64);
+            let g = Fish::init(&rules, rules.num_players as u8, seed as u64);
+            while !g.is_over() {
+                play_at_random(&g, &mut rng);
+            }
+            let summary = g.summary();
+            let declared: usize = summary.books.iter().map(Vec::len).sum();
+            assert_eq!(declared + summary.discarded.len(), rules.books().len());
+            let winner = match summary.books[0].len().cmp(&summary.books[1].len()) {
+                std::cmp::Ordering::Greater => Some(0),
+                std::cmp::Ordering::Less => Some(1),
+                std::cmp::Ordering::Equal => None,
+            };
+            assert_eq!(summary.winner, winner);
+
+            let record: Record = g.record().to_string().parse().unwrap();
+            assert_eq!(record.rules, rules);
+            let replay = Replay::new(record).unwrap();
+            replay.seek(replay.len()).unwrap();
+            assert!(replay.game().is_over());
+            assert_eq!(replay.game().summary().books, summary.books);
+        }
+    }
+
+    #[test]
+    fn rejects_a_move_the_deal_does_not_allow() {
+        let g = recorded_game(40);
+        let text = g.record().to_string();
+        let line = text
+            .lines()
+            .position(|line| line.starts_with("Ask") && line.ends_with("Yes"))
+            .unwrap();
+        let text: Vec<String> = text
+            .lines()
+            .enumerate()
+            .map(|(i, l)| {
+                if i == line {
+                    l.replace("Yes", "No")
+                } else {
+                    l.to_string()
+                }
+            })
+            .collect();
+        let record: Record = text.join("\n").parse().unwrap();
+        assert!(matches!(Replay::new(record), Err(RecordError::Illegal(..))));
+    }
+
+    #[test]
+    fn rejects_a_first_player_not_at_the_table() {
+        let mut record = recorded_game(0).record();
+        record.first_player = 9;
+        let err = record.to_string().parse::<Record>().unwrap_err();
+        assert!(matches!(err, RecordError::WrongDeal));
+    }
+}
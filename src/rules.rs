@@ -0,0 +1,246 @@
+// Table rules can be read from a TOML file,
+//
+//   players = 8
+//   deck = "go-fish"
+//   eights = false
+//   failed-declaration = "discarded"
+//   after-declaration = "declarer"
+//
+// or from the same keys written `key=value` on one line, as in game records.
+// Missing keys keep their defaults, the classic six-player game with 54 cards
+use crate::deck::{Book, Deck};
+use serde::Deserialize;
+
+/// The table rules of a game. Players sit alternately in two teams
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Rules {
+    /// 4, 6 or 8
+    #[serde(rename = "players")]
+    pub num_players: usize,
+    pub deck: DeckKind,
+    /// Play with the eights, and the jokers in a fish deck
+    pub eights: bool,
+    pub failed_declaration: FailedDeclaration,
+    pub after_declaration: AfterDeclaration,
+}
+
+/// How the cards are grouped into books
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeckKind {
+    /// Half suits of six, with the eights and jokers as a ninth book.
+    /// Without the eights this is Literature
+    Fish,
+    /// Ranks of four, with no jokers
+    GoFish,
+}
+
+/// Where the book goes when a declaration is wrong
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FailedDeclaration {
+    /// The opposing team wins it
+    Opponents,
+    /// Nobody wins it
+    Discarded,
+}
+
+/// Who moves after a declaration
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AfterDeclaration {
+    /// Whoever's turn it was
+    Unchanged,
+    /// The declarer
+    Declarer,
+    /// The declarer if they were right, otherwise the opponent to their left
+    Outcome,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            num_players: 6,
+            deck: DeckKind::Fish,
+            eights: true,
+            failed_declaration: FailedDeclaration::Opponents,
+            after_declaration: AfterDeclaration::Unchanged,
+        }
+    }
+}
+
+impl Rules {
+    /// Read rules from a TOML document
+    pub fn from_toml(text: &str) -> Result<Self, RulesError> {
+        let rules: Rules =
+            toml::from_str(text).map_err(|err| RulesError::Syntax(err.message().to_string()))?;
+        rules.validate()?;
+        Ok(rules)
+    }
+
+    /// Change one rule by its key, as written in a rules file
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), RulesError> {
+        let invalid = || RulesError::Syntax(format!("{key} cannot be {value}"));
+        match key {
+            "players" => self.num_players = value.parse().map_err(|_| invalid())?,
+            "deck" => self.deck = value.parse().map_err(|_| invalid())?,
+            "eights" => self.eights = value.parse().map_err(|_| invalid())?,
+            "failed-declaration" => {
+                self.failed_declaration = value.parse().map_err(|_| invalid())?
+            }
+            "after-declaration" => self.after_declaration = value.parse().map_err(|_| invalid())?,
+            _ => return Err(RulesError::Syntax(format!("there is no rule {key}"))),
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), RulesError> {
+        if ![4, 6, 8].contains(&self.num_players) {
+            return Err(RulesError::PlayerCount(self.num_players));
+        }
+        Ok(())
+    }
+
+    pub fn num_cards(&self) -> usize {
+        self.deck().cards().len()
+    }
+
+    /// The cards dealt and their books
+    pub fn deck(&self) -> &'static Deck {
+        match self.deck {
+            DeckKind::Fish => Deck::half_suits(self.eights),
+            DeckKind::GoFish => Deck::ranks(self.eights),
+        }
+    }
+
+    /// Books that can be declared
+    pub fn books(&self) -> Vec<Book> {
+        self.deck().books()
+    }
+
+    /// Number of cards dealt to each seat. When the deck does not split
+    /// evenly the first seats get one more
+    pub fn hand_sizes(&self) -> Vec<usize> {
+        let (n, cards) = (self.num_players, self.num_cards());
+        (0..n)
+            .map(|seat| cards / n + usize::from(seat < cards % n))
+            .collect()
+    }
+}
+
+/// Why a set of rules cannot be used
+#[derive(Clone, Debug)]
+pub enum RulesError {
+    /// Only 4, 6 or 8 players can split into two teams around the table
+    PlayerCount(usize),
+    Syntax(String),
+}
+
+impl std::error::Error for RulesError {}
+
+impl std::fmt::Display for RulesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RulesError::PlayerCount(n) => write!(f, "{n} players cannot play, only 4, 6 or 8"),
+            RulesError::Syntax(text) => write!(f, "The rules cannot be read: {text}"),
+        }
+    }
+}
+
+impl std::fmt::Display for Rules {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "players={} deck={} eights={} failed-declaration={} after-declaration={}",
+            self.num_players,
+            self.deck,
+            self.eights,
+            self.failed_declaration,
+            self.after_declaration
+        )
+    }
+}
+
+impl std::str::FromStr for Rules {
+    type Err = RulesError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rules = Rules::default();
+        for token in s.split_whitespace() {
+            let (key, value) = token
+                .split_once('=')
+                .ok_or_else(|| RulesError::Syntax(format!("expected key=value, not {token}")))?;
+            rules.set(key, value)?;
+        }
+        rules.validate()?;
+        Ok(rules)
+    }
+}
+
+impl std::fmt::Display for DeckKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeckKind::Fish => write!(f, "fish"),
+            DeckKind::GoFish => write!(f, "go-fish"),
+        }
+    }
+}
+
+impl std::str::FromStr for DeckKind {
+    type Err = RulesError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fish" => Ok(DeckKind::Fish),
+            "go-fish" => Ok(DeckKind::GoFish),
+            _ => Err(RulesError::Syntax(format!("unknown deck {s}"))),
+        }
+    }
+}
+
+impl std::fmt::Display for FailedDeclaration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FailedDeclaration::Opponents => write!(f, "opponents"),
+            FailedDeclaration::Discarded => write!(f, "discarded"),
+        }
+    }
+}
+
+impl std::str::FromStr for FailedDeclaration {
+    type Err = RulesError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "opponents" => Ok(FailedDeclaration::Opponents),
+            "discarded" => Ok(FailedDeclaration::Discarded),
+            _ => Err(RulesError::Syntax(format!(
+                "unknown failed-declaration {s}"
+            ))),
+        }
+    }
+}
+
+impl std::fmt::Display for AfterDeclaration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AfterDeclaration::Unchanged => write!(f, "unchanged"),
+            AfterDeclaration::Declarer => write!(f, "declarer"),
+            AfterDeclaration::Outcome => write!(f, "outcome"),
+        }
+    }
+}
+
+impl std::str::FromStr for AfterDeclaration {
+    type Err = RulesError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unchanged" => Ok(AfterDeclaration::Unchanged),
+            "declarer" => Ok(AfterDeclaration::Declarer),
+            "outcome" => Ok(AfterDeclaration::Outcome),
+            _ => Err(RulesError::Syntax(format!("unknown after-declaration {s}"))),
+        }
+    }
+}
@@ -0,0 +1,160 @@
+// A saved game is its record under a header giving the version of the
+// format and a seed for the bots' random numbers:
+//
+//   Fish save 1
+//   Rng 8731942004417
+//   Seed 1234
+//   Rules players=6 deck=fish eights=true failed-declaration=opponents after-declaration=unchanged
+//   ...
+//
+// Loading replays the record, which rebuilds the hands, the books, the
+// current player, the history and every engine's constraints exactly as
+// they were saved
+use crate::engine::Engine;
+use crate::game::Fish;
+use crate::record::{Record, RecordError, Replay};
+
+/// The version of the format written, and the only one read
+pub const VERSION: u32 = 1;
+
+/// Everything needed to continue a game later
+#[derive(Clone, Debug)]
+pub struct Save {
+    /// Seed the bots draw from once the game is loaded
+    pub rng_seed: u64,
+    pub record: Record,
+}
+
+impl Save {
+    /// The game as it was saved, with the engine of its public events
+    pub fn restore(&self) -> Result<(Fish, Engine), SaveError> {
+        let replay = Replay::new(self.record.clone())?;
+        replay.seek(replay.len())?;
+        let (game, engine) = replay.into_parts();
+        game.reseed(self.rng_seed);
+        Ok((game, engine))
+    }
+}
+
+/// Why a saved game cannot be loaded
+#[derive(Clone, Debug)]
+pub enum SaveError {
+    /// The text does not start with a save header
+    NotASave,
+    /// Written in a version of the format this build cannot read
+    Version(u32),
+    Record(RecordError),
+}
+
+impl From<RecordError> for SaveError {
+    fn from(err: RecordError) -> Self {
+        SaveError::Record(err)
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl std::fmt::Display for SaveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SaveError::NotASave => write!(f, "This is not a saved game"),
+            SaveError::Version(version) => {
+                write!(
+                    f,
+                    "The game was saved in version {version}, only version {VERSION} can be read"
+                )
+            }
+            SaveError::Record(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::fmt::Display for Save {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Fish save {VERSION}")?;
+        writeln!(f, "Rng {}", self.rng_seed)?;
+        write!(f, "{}", self.record)
+    }
+}
+
+impl std::str::FromStr for Save {
+    type Err = SaveError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.splitn(3, '\n');
+        let header: Vec<&str> = lines
+            .next()
+            .unwrap_or_default()
+            .split_whitespace()
+            .collect();
+        let ["Fish", "save", version] = header[..] else {
+            return Err(SaveError::NotASave);
+        };
+        let version = version.parse().map_err(|_| SaveError::NotASave)?;
+        if version != VERSION {
+            return Err(SaveError::Version(version));
+        }
+
+        let line = lines.next().unwrap_or_default();
+        let rng_seed = match line.split_whitespace().collect::<Vec<_>>()[..] {
+            ["Rng", seed] => seed.parse().ok(),
+            _ => None,
+        }
+        .ok_or_else(|| RecordError::Syntax(2, line.to_string()))?;
+
+        // Blank lines keep the record's line numbers those of the file
+        let record = format!("\n\n{}", lines.next().unwrap_or_default()).parse()?;
+        Ok(Save { rng_seed, record })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::tests::{human_table, play_at_random};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn saved_game() -> (Fish, String) {
+        let mut rng = StdRng::seed_from_u64(3);
+        let g = human_table(6, 3);
+        for _ in 0..50 {
+            play_at_random(&g, &mut rng);
+        }
+        let text = g.save().to_string();
+        (g, text)
+    }
+
+    #[test]
+    fn loads_the_hands_and_engines_it_saved() {
+        let (g, text) = saved_game();
+        let (loaded, engine) = text.parse::<Save>().unwrap().restore().unwrap();
+
+        for seat in 0..g.num_players() {
+            assert_eq!(loaded.get_hand(seat), g.get_hand(seat));
+            assert_eq!(
+                loaded.perspective(seat).domains(),
+                g.perspective(seat).domains()
+            );
+        }
+        assert_eq!(loaded.curr_player(), g.curr_player());
+        assert_eq!(loaded.summary().books, g.summary().books);
+
+        let public = Engine::init(&human_table(6, 3));
+        for event in g.record().events.iter() {
+            public.update_constraints(event).unwrap();
+        }
+        assert_eq!(engine.domains(), public.domains());
+    }
+
+    #[test]
+    fn rejects_other_versions_and_plain_records() {
+        let (g, text) = saved_game();
+        let newer = text.replacen(&format!("Fish save {VERSION}"), "Fish save 2", 1);
+        assert!(matches!(newer.parse::<Save>(), Err(SaveError::Version(2))));
+        assert!(matches!(
+            g.record().to_string().parse::<Save>(),
+            Err(SaveError::NotASave)
+        ));
+    }
+}
@@ -0,0 +1,486 @@
+// A game hosted over TCP so players can sit at separate machines. Clients
+// send one command per line and the server answers one message per line:
+//
+//   client                        server
+//                                 rules players=6 deck=fish ...
+//                                 free 0 1 2 3 4 5
+//   seat 2                        seat 2
+//                                 start
+//                                 hand 3♦,5♣,J♣,...
+//                                 sizes 9 9 9 9 9 9
+//                                 turn 4
+//                                 event Ask 4 1 2♦ Yes
+//   ask 3 2♦
+//   declare LD 0:2♦ 2:3♦,4♦,5♦,6♦,7♦
+//   pass 4
+//   hand
+//   quit
+//                                 error It is not your turn
+//                                 over 5 4
+//
+// `seat` without a number takes the first free seat. Events are written as
+// in game records. A client is only ever sent its own hand, other seats are
+// known by their number of cards. Seats nobody claims are played by bots,
+// and so is the seat of a client that disconnects
+use crate::card::Card;
+use crate::game::{parse_claim, AskError, DeclareError, Event, Fish, NextError, PassError};
+use crate::record::EventLine;
+use crate::strategy;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+
+// What the connection threads tell the game thread
+enum Message {
+    Connected(usize, TcpStream),
+    Line(usize, String),
+    Disconnected(usize),
+}
+
+/// Hosts one game, taking seats for the connections that claim them
+pub struct Server {
+    game: Fish,
+    /// Strategy of the seats no connection holds
+    bot: String,
+    /// Seats to claim before play starts
+    humans: usize,
+    started: bool,
+    clients: HashMap<usize, TcpStream>,
+    /// The connection holding each seat
+    seats: Vec<Option<usize>>,
+}
+
+impl Server {
+    /// None if `bot` is not a known strategy or more humans are expected
+    /// than there are seats
+    pub fn new(game: Fish, bot: &str, humans: usize) -> Option<Self> {
+        strategy::by_name(bot)?;
+        if humans > game.num_players() {
+            return None;
+        }
+        for seat in 0..game.num_players() {
+            game.set_strategy(seat, strategy::by_name(bot));
+        }
+        let seats = vec![None; game.num_players()];
+        Some(Server {
+            game,
+            bot: bot.to_string(),
+            humans,
+            started: false,
+            clients: HashMap::new(),
+            seats,
+        })
+    }
+
+    pub fn game(&self) -> &Fish {
+        &self.game
+    }
+
+    /// Accept connections and play until every book is declared, calling
+    /// `on_event` after each move
+    pub fn run(
+        &mut self,
+        listener: TcpListener,
+        mut on_event: impl FnMut(&Fish, &Event),
+    ) -> std::io::Result<()> {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || accept(listener, tx));
+
+        if self.humans == 0 {
+            self.start();
+        }
+        while !self.game.is_over() {
+            if self.started && self.game.is_bot(self.game.curr_player()) {
+                match self.game.handle_next() {
+                    Ok(event) => {
+                        self.announce(&event);
+                        on_event(&self.game, &event);
+                        continue;
+                    }
+                    Err(NextError::IllegalAction(action)) => {
+                        return Err(std::io::Error::other(format!(
+                            "The bot tried an illegal move: {action:?}"
+                        )));
+                    }
+                    Err(_) => {}
+                }
+            }
+            let Ok(message) = rx.recv() else {
+                break;
+            };
+            match message {
+                Message::Connected(id, stream) => {
+                    self.clients.insert(id, stream);
+                    self.send(id, &format!("rules {}", self.game.rules()));
+                    self.send_free_seats(id);
+                    if self.started {
+                        self.send_state(id);
+                    }
+                }
+                Message::Line(id, line) => {
+                    if let Some(event) = self.handle(id, &line) {
+                        self.announce(&event);
+                        on_event(&self.game, &event);
+                    }
+                }
+                Message::Disconnected(id) => self.leave(id),
+            }
+        }
+
+        let summary = self.game.summary();
+        let over = format!("over {} {}", summary.books[0].len(), summary.books[1].len());
+        for id in self.clients.keys().copied().collect::<Vec<_>>() {
+            self.send(id, &over);
+        }
+        Ok(())
+    }
+
+    // Carry out one command, returning the move it made if any
+    fn handle(&mut self, id: usize, line: &str) -> Option<Event> {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let seat = self.seats.iter().position(|holder| *holder == Some(id));
+        let result = match (&tokens[..], seat) {
+            ([], _) => return None,
+            (["seat"], None) => {
+                let free = self.seats.iter().position(|holder| holder.is_none());
+                self.claim(id, free)
+            }
+            (["seat", n], None) => self.claim(id, n.parse().ok()),
+            (["seat", ..], Some(_)) => Err("You already have a seat".to_string()),
+            (["hand"], Some(seat)) => {
+                self.send_hand(id, seat);
+                return None;
+            }
+            (["quit"], _) => {
+                self.leave(id);
+                return None;
+            }
+            (["hand" | "ask" | "declare" | "pass", ..], None) => {
+                Err("Take a seat first".to_string())
+            }
+            (["ask" | "declare" | "pass", ..], Some(_)) if !self.started => {
+                Err("Waiting for players to take their seats".to_string())
+            }
+            (["ask" | "declare" | "pass", ..], Some(seat)) if seat != self.game.curr_player() => {
+                Err("It is not your turn".to_string())
+            }
+            (["ask", askee, card], Some(_)) => self.ask(askee, card),
+            (["declare", book, claims @ ..], Some(_)) => self.declare(book, claims),
+            (["pass"], Some(_)) => self.pass(None),
+            (["pass", to], Some(_)) => match to.parse() {
+                Ok(to) => self.pass(Some(to)),
+                Err(_) => Err("That player does not exist".to_string()),
+            },
+            _ => Err(format!("Unknown command {line}")),
+        };
+        match result {
+            Ok(event) => event,
+            Err(err) => {
+                self.send(id, &format!("error {err}"));
+                None
+            }
+        }
+    }
+
+    fn claim(&mut self, id: usize, seat: Option<usize>) -> Result<Option<Event>, String> {
+        let seat = seat
+            .filter(|seat| *seat < self.seats.len())
+            .ok_or("That seat does not exist")?;
+        if self.seats[seat].is_some() {
+            return Err("That seat is taken".to_string());
+        }
+        self.seats[seat] = Some(id);
+        self.game.set_strategy(seat, None);
+        self.send(id, &format!("seat {seat}"));
+        if self.started {
+            self.send_state(id);
+        } else if self.seats.iter().flatten().count() == self.humans {
+            self.start();
+        }
+        Ok(None)
+    }
+
+    // A client that leaves hands its seat back to a bot
+    fn leave(&mut self, id: usize) {
+        if let Some(seat) = self.seats.iter().position(|holder| *holder == Some(id)) {
+            self.seats[seat] = None;
+            self.game.set_strategy(seat, strategy::by_name(&self.bot));
+        }
+        if let Some(stream) = self.clients.remove(&id) {
+            let _ = stream.shutdown(std::net::Shutdown::Both);
+        }
+    }
+
+    fn start(&mut self) {
+        self.started = true;
+        for id in self.clients.keys().copied().collect::<Vec<_>>() {
+            self.send(id, "start");
+            self.send_state(id);
+        }
+    }
+
+    fn ask(&self, askee: &str, card: &str) -> Result<Option<Event>, String> {
+        let askee = askee.parse().map_err(|_| "That player does not exist")?;
+        let card = card.parse::<Card>().map_err(|err| err.to_string())?;
+        let message = match self.game.handle_ask(askee, &card) {
+            Ok(ask) => return Ok(Some(Event::Ask(ask))),
+            Err(AskError::GameOver) => "The game is over",
+            Err(AskError::BotTurn) => "It is a bot's turn",
+            Err(AskError::SameTeam) => "You cannot ask someone on your team",
+            Err(AskError::PlayerNotFound) => "That player does not exist",
+            Err(AskError::InvalidBook) => "You do not have this book in your hand",
+            Err(AskError::AlreadyOwnCard) => "You have the card",
+            Err(AskError::NoCards) => "That player has no cards left",
+        };
+        Err(message.to_string())
+    }
+
+    fn declare(&self, book: &str, claims: &[&str]) -> Result<Option<Event>, String> {
+        let book = self.game.deck().book(book).map_err(|err| err.to_string())?;
+        let mut claimed_cards = HashMap::new();
+        for claim in claims {
+            let (holder, cards) =
+                parse_claim(claim).ok_or(format!("Expected seat:cards, not {claim}"))?;
+            *claimed_cards.entry(holder).or_default() |= cards;
+        }
+        let message = match self.game.handle_declare(book, claimed_cards) {
+            Ok(declare) => return Ok(Some(Event::Declare(declare))),
+            Err(DeclareError::GameOver) => "The game is over",
+            Err(DeclareError::BotTurn) => "It is a bot's turn",
+            Err(DeclareError::PlayerNotFound) => "That player does not exist",
+            Err(DeclareError::AlreadyDeclared) => "That book was already declared",
+            Err(DeclareError::NotTeammate) => "You can only name players on your team",
+            Err(DeclareError::WrongBook) => "Those cards are not all in the book",
+            Err(DeclareError::Incomplete) => "Every card of the book must be named exactly once",
+        };
+        Err(message.to_string())
+    }
+
+    fn pass(&self, to: Option<usize>) -> Result<Option<Event>, String> {
+        let message = match self.game.handle_pass(to) {
+            Ok(pass) => return Ok(Some(Event::PassTurn(pass))),
+            Err(PassError::GameOver) => "The game is over",
+            Err(PassError::BotTurn) => "It is a bot's turn",
+            Err(PassError::PlayerNotFound) => "That player does not exist",
+            Err(PassError::HasCards) => "You can still ask for a card",
+            Err(PassError::NoCards) => "That player has no cards either",
+            Err(PassError::NotTeammate) => "Pass to a teammate while one still has cards",
+        };
+        Err(message.to_string())
+    }
+
+    // Tell every client about a move and where it leaves them
+    fn announce(&mut self, event: &Event) {
+        let line = format!("event {}", EventLine(event));
+        for id in self.clients.keys().copied().collect::<Vec<_>>() {
+            self.send(id, &line);
+            self.send_state(id);
+        }
+    }
+
+    // The client's own hand, if seated, and what everyone can see
+    fn send_state(&mut self, id: usize) {
+        if let Some(seat) = self.seats.iter().position(|holder| *holder == Some(id)) {
+            self.send_hand(id, seat);
+        }
+        let sizes: Vec<String> = self
+            .game
+            .players()
+            .iter()
+            .map(|p| p.cards.len().to_string())
+            .collect();
+        self.send(id, &format!("sizes {}", sizes.join(" ")));
+        self.send(id, &format!("turn {}", self.game.curr_player()));
+    }
+
+    fn send_hand(&mut self, id: usize, seat: usize) {
+        self.send(id, &format!("hand {}", self.game.get_hand(seat)));
+    }
+
+    fn send_free_seats(&mut self, id: usize) {
+        let free: Vec<String> = (0..self.seats.len())
+            .filter(|seat| self.seats[*seat].is_none())
+            .map(|seat| seat.to_string())
+            .collect();
+        self.send(id, &format!("free {}", free.join(" ")));
+    }
+
+    // A client that cannot be written to is gone, its reader will notice
+    fn send(&mut self, id: usize, line: &str) {
+        if let Some(stream) = self.clients.get_mut(&id) {
+            let _ = writeln!(stream, "{line}");
+        }
+    }
+}
+
+// Hand every connection to the game thread and read its lines on a thread
+// of its own
+fn accept(listener: TcpListener, tx: Sender<Message>) {
+    for (id, stream) in listener.incoming().enumerate() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let Ok(writer) = stream.try_clone() else {
+            continue;
+        };
+        if tx.send(Message::Connected(id, writer)).is_err() {
+            return;
+        }
+        let tx = tx.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(stream).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if tx.send(Message::Line(id, line)).is_err() {
+                    return;
+                }
+            }
+            let _ = tx.send(Message::Disconnected(id));
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::{self, ServerMessage, Table};
+    use crate::game::tests::{human_table, play_at_random};
+    use crate::game::{Ask, Declare, PassTurn};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::sync::mpsc::Receiver;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    // The command a client sends to make the move
+    fn command(event: &Event) -> String {
+        match event {
+            Event::Ask(Ask { askee, card, .. }) => format!("ask {askee} {card}"),
+            Event::Declare(Declare {
+                book,
+                claimed_cards,
+                ..
+            }) => {
+                let claims: Vec<String> = claimed_cards
+                    .iter()
+                    .map(|(seat, cards)| format!("{seat}:{cards}"))
+                    .collect();
+                format!("declare {book} {}", claims.join(" "))
+            }
+            Event::PassTurn(PassTurn { receiver, .. }) => format!("pass {receiver}"),
+        }
+    }
+
+    // What each seat is sent up to the first message that is `last`
+    fn read_until(
+        seats: &[Receiver<ServerMessage>],
+        last: fn(&ServerMessage) -> bool,
+    ) -> Vec<Vec<ServerMessage>> {
+        let read = |rx: &Receiver<ServerMessage>| {
+            let mut messages = vec![];
+            while !messages.last().is_some_and(last) {
+                messages.push(rx.recv_timeout(Duration::from_secs(10)).unwrap());
+            }
+            messages
+        };
+        seats.iter().map(read).collect()
+    }
+
+    #[test]
+    fn clients_in_every_seat_play_a_game_to_the_end() {
+        let num_players = 4;
+        let seed = 3;
+        // The same deal played out here decides the moves and what each
+        // client should be told
+        let g = human_table(num_players, seed);
+        let rules = g.rules();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let game = Fish::init(&rules, num_players as u8, seed);
+            let mut server = Server::new(game, "random", num_players).unwrap();
+            server.run(listener, |_, _| {}).is_ok()
+        });
+
+        let mut writers = vec![];
+        let mut seats = vec![];
+        let mut clients = vec![];
+        for seat in 0..num_players {
+            let stream = TcpStream::connect(addr).unwrap();
+            stream.set_nodelay(true).unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            writeln!(writer, "seat {seat}").unwrap();
+            writers.push(writer);
+            let (tx, rx) = mpsc::channel();
+            seats.push(rx);
+            clients.push(thread::spawn(move || {
+                let table = Mutex::new(Table::default());
+                client::listen(stream, &table, |_, message| {
+                    let _ = tx.send(message.clone());
+                });
+                table.into_inner().unwrap()
+            }));
+        }
+
+        let is_turn = |message: &ServerMessage| matches!(message, ServerMessage::Turn(_));
+        let mut events = vec![];
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut messages = read_until(&seats, is_turn);
+        loop {
+            for (seat, seen) in messages.iter().enumerate() {
+                let hands: Vec<_> = seen
+                    .iter()
+                    .filter_map(|message| match message {
+                        ServerMessage::Hand(hand) => Some(*hand),
+                        _ => None,
+                    })
+                    .collect();
+                assert_eq!(hands, vec![g.get_hand(seat)]);
+                let sizes: Vec<usize> = g.players().iter().map(|p| p.cards.len()).collect();
+                assert!(seen
+                    .iter()
+                    .any(|message| matches!(message, ServerMessage::Sizes(s) if *s == sizes)));
+                assert!(seen
+                    .iter()
+                    .all(|message| !matches!(message, ServerMessage::Error(_))));
+                let told: Vec<String> = seen
+                    .iter()
+                    .filter_map(|message| match message {
+                        ServerMessage::Event(event) => Some(EventLine(event).to_string()),
+                        _ => None,
+                    })
+                    .collect();
+                assert_eq!(told, events);
+                assert!(matches!(seen.last(), Some(ServerMessage::Turn(turn))
+                    if *turn == g.curr_player()));
+            }
+            if g.is_over() {
+                break;
+            }
+            let seat = g.curr_player();
+            let event = play_at_random(&g, &mut rng);
+            writeln!(writers[seat], "{}", command(&event)).unwrap();
+            events = vec![EventLine(&event).to_string()];
+            messages = read_until(&seats, is_turn);
+        }
+
+        let summary = g.summary();
+        let books = [summary.books[0].len(), summary.books[1].len()];
+        let is_over = |message: &ServerMessage| matches!(message, ServerMessage::Over(_));
+        for seen in read_until(&seats, is_over) {
+            assert!(matches!(seen[..], [ServerMessage::Over(over)] if over == books));
+        }
+        assert!(server.join().unwrap());
+        for (seat, (writer, client)) in writers.into_iter().zip(clients).enumerate() {
+            writer.shutdown(std::net::Shutdown::Both).unwrap();
+            let table = client.join().unwrap();
+            assert_eq!(table.seat, Some(seat));
+            assert!(table.started);
+            assert_eq!(table.log.len(), g.num_events());
+            assert_eq!(table.result, Some(books));
+        }
+    }
+}
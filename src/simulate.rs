@@ -0,0 +1,221 @@
+// Bot-only games played without the REPL, for comparing strategies. Every
+// pair of strategies plays each deal twice with the teams swapped, so luck
+// of the deal and of moving first cancels out between them
+use crate::game::Fish;
+use crate::rules::Rules;
+use crate::strategy;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// Games still going after this many moves are stopped and scored as they stand
+const MAX_MOVES: usize = 2000;
+
+// Two-sided 95% quantile of the normal distribution
+const Z: f64 = 1.96;
+
+/// How a bot-only game came out
+#[derive(Clone, Debug)]
+pub struct GameResult {
+    /// Books won by each team
+    pub books: [usize; 2],
+    pub moves: usize,
+    /// Whether every book was declared before play stopped
+    pub finished: bool,
+}
+
+impl GameResult {
+    /// The team with more books, None for a tie
+    pub fn winner(&self) -> Option<usize> {
+        match self.books[0].cmp(&self.books[1]) {
+            std::cmp::Ordering::Greater => Some(0),
+            std::cmp::Ordering::Less => Some(1),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+}
+
+/// Let the bots play until the game is over, a bot cannot move or
+/// `max_moves` moves have been made
+pub fn play_out(g: &Fish, max_moves: usize) -> GameResult {
+    let mut moves = 0;
+    while moves < max_moves && g.handle_next().is_ok() {
+        moves += 1;
+    }
+    let summary = g.summary();
+    GameResult {
+        books: [summary.books[0].len(), summary.books[1].len()],
+        moves,
+        finished: g.is_over(),
+    }
+}
+
+/// How one strategy did across a tournament
+#[derive(Clone, Debug, Default)]
+pub struct Standing {
+    pub name: String,
+    pub games: usize,
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+    /// Books won by the strategy's team over all its games
+    pub books: usize,
+    /// Games stopped before every book was declared
+    pub unfinished: usize,
+}
+
+impl Standing {
+    /// Wins plus half the draws, as a share of games played
+    pub fn win_rate(&self) -> f64 {
+        if self.games == 0 {
+            return 0.0;
+        }
+        (self.wins as f64 + 0.5 * self.draws as f64) / self.games as f64
+    }
+
+    /// Wilson score interval holding the true win rate with 95% confidence
+    pub fn confidence_interval(&self) -> (f64, f64) {
+        if self.games == 0 {
+            return (0.0, 1.0);
+        }
+        let n = self.games as f64;
+        let p = self.win_rate();
+        let denominator = 1.0 + Z * Z / n;
+        let center = (p + Z * Z / (2.0 * n)) / denominator;
+        let margin = Z * (p * (1.0 - p) / n + Z * Z / (4.0 * n * n)).sqrt() / denominator;
+        ((center - margin).max(0.0), (center + margin).min(1.0))
+    }
+
+    fn add(&mut self, result: &GameResult, team: usize) {
+        self.games += 1;
+        self.books += result.books[team];
+        match result.winner() {
+            Some(winner) if winner == team => self.wins += 1,
+            Some(_) => self.losses += 1,
+            None => self.draws += 1,
+        }
+        if !result.finished {
+            self.unfinished += 1;
+        }
+    }
+}
+
+/// Every pair of strategies playing the same seeded deals from both sides
+#[derive(Debug)]
+pub struct Tournament {
+    rules: Rules,
+    strategies: Vec<String>,
+    num_deals: usize,
+    seed: u64,
+}
+
+impl Tournament {
+    /// None unless there are at least two strategies and `strategy::by_name`
+    /// knows all of them. A strategy may be named twice to play itself
+    pub fn new(rules: Rules, strategies: Vec<String>, num_deals: usize, seed: u64) -> Option<Self> {
+        if strategies.len() < 2
+            || strategies
+                .iter()
+                .any(|name| strategy::by_name(name).is_none())
+        {
+            return None;
+        }
+        Some(Tournament {
+            rules,
+            strategies,
+            num_deals,
+            seed,
+        })
+    }
+
+    /// Number of games `run` plays
+    pub fn num_games(&self) -> usize {
+        let n = self.strategies.len();
+        self.num_deals * n * (n - 1)
+    }
+
+    /// Play every game, calling `on_game` after each with the strategy of
+    /// each team and the result
+    pub fn run(&self, mut on_game: impl FnMut([&str; 2], &GameResult)) -> Vec<Standing> {
+        let mut standings: Vec<Standing> = self
+            .strategies
+            .iter()
+            .map(|name| Standing {
+                name: name.clone(),
+                ..Standing::default()
+            })
+            .collect();
+
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        for _ in 0..self.num_deals {
+            let deal_seed = rng.random();
+            for a in 0..self.strategies.len() {
+                for b in (a + 1)..self.strategies.len() {
+                    for teams in [[a, b], [b, a]] {
+                        let names = teams.map(|i| self.strategies[i].as_str());
+                        let result = self.play(deal_seed, names);
+                        standings[teams[0]].add(&result, 0);
+                        standings[teams[1]].add(&result, 1);
+                        on_game(names, &result);
+                    }
+                }
+            }
+        }
+        standings
+    }
+
+    // The same seed gives the same deal, first player and bot randomness, so
+    // only the strategies differ between the two games of a deal
+    fn play(&self, seed: u64, names: [&str; 2]) -> GameResult {
+        let g = Fish::init(&self.rules, 0, seed);
+        for seat in 0..g.num_players() {
+            g.set_strategy(seat, strategy::by_name(names[seat % 2]));
+        }
+        play_out(&g, MAX_MOVES)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stops_a_game_after_the_last_move_allowed() {
+        let g = Fish::init(&Rules::default(), 0, 1);
+        let result = play_out(&g, 3);
+        assert_eq!(result.moves, 3);
+        assert!(!result.finished);
+        assert_eq!(g.record().events.len(), 3);
+    }
+
+    #[test]
+    fn counts_draws_as_half_a_win() {
+        let mut standing = Standing::default();
+        for books in [[5, 4], [4, 5], [4, 4], [6, 3]] {
+            let result = GameResult {
+                books,
+                moves: 100,
+                finished: true,
+            };
+            standing.add(&result, 0);
+        }
+        assert_eq!((standing.wins, standing.draws, standing.losses), (2, 1, 1));
+        assert_eq!(standing.win_rate(), 0.625);
+        let (low, high) = standing.confidence_interval();
+        assert!(low < 0.625 && 0.625 < high);
+    }
+
+    #[test]
+    fn needs_two_known_strategies_and_counts_the_games() {
+        let names = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        assert!(Tournament::new(Rules::default(), names(&["smart"]), 1, 0).is_none());
+        assert!(Tournament::new(Rules::default(), names(&["smart", "nobody"]), 1, 0).is_none());
+        let tournament = Tournament::new(
+            Rules::default(),
+            names(&["smart", "random", "ismcts"]),
+            4,
+            0,
+        )
+        .unwrap();
+        assert_eq!(tournament.num_games(), 24);
+    }
+}
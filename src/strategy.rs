@@ -0,0 +1,289 @@
+use crate::card::{Card, CardSet};
+use crate::deck::Book;
+use crate::engine::{Deal, Engine};
+use crate::game::Event;
+use crate::ismcts::IsmctsBot;
+use rand::rngs::StdRng;
+use rand::seq::IndexedRandom;
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+// Deals sampled when no declaration can be proven
+const DECLARE_SAMPLES: usize = 200;
+
+/// What a player does on their turn
+#[derive(Clone, Debug)]
+pub enum Action {
+    Ask {
+        askee: usize,
+        card: Card,
+    },
+    Declare {
+        book: Book,
+        claimed_cards: HashMap<usize, CardSet>,
+    },
+    /// Hand the turn on after running out of cards, None for the next
+    /// teammate who has some
+    PassTurn {
+        to: Option<usize>,
+    },
+}
+
+/// Everything a seat can observe when it is their turn
+pub struct View<'a> {
+    pub seat: usize,
+    pub hand: CardSet,
+    pub num_players: usize,
+    /// Number of cards each seat holds, which everyone can count
+    pub hand_sizes: Vec<usize>,
+    /// Every public event so far, oldest first
+    pub history: &'a [Event],
+    /// What the seat can deduce from the history and its own hand
+    pub engine: &'a Engine,
+}
+
+/// A way of playing a seat. Any randomness must come from `rng` so that
+/// seeded games replay exactly
+pub trait Strategy: Debug {
+    /// Short name used in game records and on the command line
+    fn name(&self) -> &'static str;
+
+    /// The move to make, None if the seat can neither ask nor declare,
+    /// which hands the turn on
+    fn choose(&mut self, view: &View, rng: &mut StdRng) -> Option<Action>;
+
+    /// Who gets the turn once the seat has run out of cards, None for the
+    /// next teammate who has some
+    fn pass_to(&mut self, _view: &View, _rng: &mut StdRng) -> Option<usize> {
+        None
+    }
+}
+
+/// Names accepted by `by_name`
+pub const STRATEGIES: [&str; 3] = ["random", "smart", "ismcts"];
+
+/// The strategy with the given name, set up with its defaults. ISMCTS takes
+/// settings after a colon, e.g. `ismcts:iterations=200,time=500`
+pub fn by_name(name: &str) -> Option<Box<dyn Strategy>> {
+    match name.split_once(':') {
+        None => match name {
+            "random" => Some(Box::new(RandomBot::default())),
+            "smart" => Some(Box::new(SmartBot::default())),
+            "ismcts" => Some(Box::new(IsmctsBot::default())),
+            _ => None,
+        },
+        Some(("ismcts", settings)) => Some(Box::new(IsmctsBot::with_settings(settings)?)),
+        Some(_) => None,
+    }
+}
+
+/// Declares whenever its engine places a book within the team and
+/// otherwise asks a random opponent for a random card it may ask for
+#[derive(Debug)]
+pub struct RandomBot {
+    /// Share of sampled deals allowed to contradict a declaration
+    pub risk_tolerance: f32,
+}
+
+impl Default for RandomBot {
+    fn default() -> Self {
+        RandomBot {
+            risk_tolerance: 0.1,
+        }
+    }
+}
+
+impl Strategy for RandomBot {
+    fn name(&self) -> &'static str {
+        "random"
+    }
+
+    fn choose(&mut self, view: &View, rng: &mut StdRng) -> Option<Action> {
+        if let Some(declare) = declaration(view, self.risk_tolerance, rng) {
+            return Some(declare);
+        }
+        match legal_asks(view).choose(rng) {
+            Some((askee, card)) => Some(Action::Ask {
+                askee: *askee,
+                card: *card,
+            }),
+            None => no_legal_ask(view, rng),
+        }
+    }
+}
+
+/// Asks the opponent most likely to hold a card, favouring books the team
+/// is close to completing and books it has already shown it holds
+#[derive(Debug)]
+pub struct SmartBot {
+    /// Share of sampled deals allowed to contradict a declaration
+    pub risk_tolerance: f32,
+    /// Deals sampled each turn to estimate who holds what
+    pub num_samples: usize,
+    /// Bonus for asking into a book by the share of it the team holds
+    pub progress_weight: f32,
+    /// Penalty for revealing a book by the share of it the bot would expose
+    pub exposure_weight: f32,
+}
+
+impl Default for SmartBot {
+    fn default() -> Self {
+        SmartBot {
+            risk_tolerance: 0.1,
+            num_samples: 200,
+            progress_weight: 0.5,
+            exposure_weight: 0.3,
+        }
+    }
+}
+
+impl Strategy for SmartBot {
+    fn name(&self) -> &'static str {
+        "smart"
+    }
+
+    fn choose(&mut self, view: &View, rng: &mut StdRng) -> Option<Action> {
+        if let Some(declare) = proven_declaration(view) {
+            return Some(declare);
+        }
+        let deals = view.engine.sample_deals(self.num_samples, rng);
+        if let Some(declare) = likely_declaration(view, self.risk_tolerance, &deals) {
+            return Some(declare);
+        }
+
+        // How often each player holds each card across the deals
+        let mut held = vec![[0.0f32; 64]; view.num_players];
+        for deal in deals.iter() {
+            for (player, cards) in deal.iter() {
+                for card in cards.iter() {
+                    held[*player][card.num as usize] += 1.0 / deals.len() as f32;
+                }
+            }
+        }
+        let team: Vec<usize> = (0..view.num_players)
+            .filter(|p| p % 2 == view.seat % 2)
+            .collect();
+        let shown = shown_books(view);
+        let deck = view.engine.deck();
+
+        let score = |askee: usize, card: Card| {
+            let book = deck
+                .book_of(card)
+                .expect("Only cards of the deck are asked for");
+            let size = book.mask().len() as f32;
+            let progress = book
+                .mask()
+                .iter()
+                .map(|c| team.iter().map(|p| held[*p][c.num as usize]).sum::<f32>())
+                .sum::<f32>()
+                / size;
+            let exposure = if shown.contains(&book) {
+                0.0
+            } else {
+                (view.hand & book.mask()).len() as f32 / size
+            };
+            held[askee][card.num as usize] * (1.0 + self.progress_weight * progress)
+                - self.exposure_weight * exposure
+        };
+
+        legal_asks(view)
+            .into_iter()
+            .max_by(|a, b| score(a.0, a.1).total_cmp(&score(b.0, b.1)))
+            .map(|(askee, card)| Action::Ask { askee, card })
+            .or_else(|| no_legal_ask(view, rng))
+    }
+
+    // The teammate with the most cards has the most to ask with
+    fn pass_to(&mut self, view: &View, _rng: &mut StdRng) -> Option<usize> {
+        (0..view.num_players)
+            .filter(|p| *p != view.seat && p % 2 == view.seat % 2 && view.hand_sizes[*p] > 0)
+            .max_by_key(|p| view.hand_sizes[*p])
+    }
+}
+
+// With every opponent out of cards the team holds all that is left and
+// has to declare, however unsure. None if no book can be with the team
+pub(crate) fn no_legal_ask(view: &View, rng: &mut StdRng) -> Option<Action> {
+    let deals = view.engine.sample_deals(DECLARE_SAMPLES, rng);
+    likely_declaration(view, 1.0, &deals)
+}
+
+/// Books the seat has already shown it holds by asking for their cards
+pub fn shown_books(view: &View) -> Vec<Book> {
+    view.history
+        .iter()
+        .filter_map(|event| match event {
+            Event::Ask(ask) if ask.asker == view.seat => view.engine.deck().book_of(ask.card),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Every (opponent with cards, card) pair the seat may ask for
+pub fn legal_asks(view: &View) -> Vec<(usize, Card)> {
+    let books = view
+        .engine
+        .deck()
+        .books()
+        .into_iter()
+        .filter(|book| view.hand.intersects(book.mask()))
+        .fold(CardSet::new(), |cards, book| cards | book.mask());
+    let askable = (books & view.engine.live_cards()) - view.hand;
+    (0..view.num_players)
+        .filter(|p| p % 2 != view.seat % 2 && view.hand_sizes[*p] > 0)
+        .flat_map(|p| askable.iter().map(move |card| (p, card)))
+        .collect()
+}
+
+/// A book the seat can place within its team. Proven placements come first,
+/// otherwise the likeliest one if at most `risk_tolerance` of sampled deals
+/// contradict it
+pub fn declaration(view: &View, risk_tolerance: f32, rng: &mut StdRng) -> Option<Action> {
+    if let Some(declare) = proven_declaration(view) {
+        return Some(declare);
+    }
+    let team = view.seat % 2;
+    if !view
+        .engine
+        .books_in_play()
+        .into_iter()
+        .any(|book| view.engine.may_be_within(book, team))
+    {
+        return None;
+    }
+    let deals = view.engine.sample_deals(DECLARE_SAMPLES, rng);
+    likely_declaration(view, risk_tolerance, &deals)
+}
+
+/// A book the engine proves is held entirely by the seat's team
+pub fn proven_declaration(view: &View) -> Option<Action> {
+    let team = view.seat % 2;
+    view.engine.books_in_play().into_iter().find_map(|book| {
+        view.engine
+            .located_within(book, team)
+            .map(|claimed_cards| Action::Declare {
+                book,
+                claimed_cards,
+            })
+    })
+}
+
+/// The book whose likeliest placement within the team is contradicted by
+/// the fewest deals, if no more than `risk_tolerance` of them
+pub fn likely_declaration(view: &View, risk_tolerance: f32, deals: &[Deal]) -> Option<Action> {
+    let team = view.seat % 2;
+    view.engine
+        .books_in_play()
+        .into_iter()
+        .filter(|book| view.engine.may_be_within(*book, team))
+        .filter_map(|book| {
+            let (claimed_cards, p) = view.engine.likely_within(book, team, deals)?;
+            Some((book, claimed_cards, p))
+        })
+        .filter(|(_, _, p)| *p >= 1.0 - risk_tolerance)
+        .max_by(|a, b| a.2.total_cmp(&b.2))
+        .map(|(book, claimed_cards, _)| Action::Declare {
+            book,
+            claimed_cards,
+        })
+}